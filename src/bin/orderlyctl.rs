@@ -0,0 +1,230 @@
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "orderlyctl [-socket PATH] [-json] COMMAND
+
+Control a running orderly instance via its -control-socket.
+
+FLAGS:
+  -socket PATH    Path of the control socket, defaults to $ORDERLY_CONTROL_SOCKET.
+  -json           Print json instead of human readable output.
+  -timeout SECS   Give up on wait-running after SECS seconds (default=120).
+
+COMMANDS:
+  status          Show the supervisor and process status.
+  restart NAME    Restart the named process.
+  restart-all     Restart all processes.
+  reload          Reload the config file, restarting only changed processes.
+  stop            Shutdown gracefully, equivalent to SIGINT.
+  terminate       Kill all processes, equivalent to SIGTERM.
+  logs NAME       Print the recent output of the named process.
+  wait-running    Wait until all processes have started.
+";
+
+fn usage() -> ! {
+  print!("{}", USAGE);
+  std::process::exit(0);
+}
+
+fn die(s: &str) -> ! {
+  eprintln!("orderlyctl: {}", s);
+  std::process::exit(1);
+}
+
+fn send_command(socket: &str, command: &str) -> Result<String, std::io::Error> {
+  let mut conn = std::os::unix::net::UnixStream::connect(socket)?;
+  conn.write_all(command.as_bytes())?;
+  conn.write_all(b"\n")?;
+  let mut response = String::new();
+  conn.read_to_string(&mut response)?;
+  Ok(response.trim_end().to_string())
+}
+
+fn format_exit(exit: &serde_json::Value) -> String {
+  if let Some(code) = exit["code"].as_i64() {
+    format!("code {}", code)
+  } else if let Some(signal) = exit["signal"].as_i64() {
+    format!("signal {}", signal)
+  } else {
+    String::from("-")
+  }
+}
+
+fn format_check(check: &serde_json::Value, now: f64) -> String {
+  match (check["ok"].as_bool(), check["time"].as_f64()) {
+    (Some(ok), Some(time)) => format!(
+      "{} {:.0}s ago",
+      if ok { "ok" } else { "failed" },
+      (now - time).max(0.0)
+    ),
+    _ => String::from("-"),
+  }
+}
+
+fn print_status(status: &serde_json::Value) {
+  let now = status["time"].as_f64().unwrap_or(0.0);
+
+  println!("state: {}", status["state"].as_str().unwrap_or("unknown"));
+  println!(
+    "restart tokens: {:.2}",
+    status["restart_tokens"].as_f64().unwrap_or(0.0)
+  );
+
+  let mut rows = vec![vec![
+    String::from("NAME"),
+    String::from("PHASE"),
+    String::from("PID"),
+    String::from("RESTARTS"),
+    String::from("LAST EXIT"),
+    String::from("LAST CHECK"),
+    String::from("STATUS"),
+  ]];
+  if let Some(procs) = status["procs"].as_array() {
+    for p in procs {
+      rows.push(vec![
+        p["name"].as_str().unwrap_or("").to_string(),
+        p["phase"].as_str().unwrap_or("").replace('_', " "),
+        match p["pid"].as_u64() {
+          Some(pid) => pid.to_string(),
+          None => String::from("-"),
+        },
+        p["restarts"].as_u64().unwrap_or(0).to_string(),
+        format_exit(&p["last_exit"]),
+        format_check(&p["last_check"], now),
+        p["status_text"].as_str().unwrap_or("-").to_string(),
+      ]);
+    }
+  }
+
+  let mut widths = vec![0; rows[0].len()];
+  for row in rows.iter() {
+    for (i, col) in row.iter().enumerate() {
+      widths[i] = widths[i].max(col.len());
+    }
+  }
+
+  for row in rows.iter() {
+    let cols: Vec<String> = row
+      .iter()
+      .enumerate()
+      .map(|(i, col)| format!("{:width$}", col, width = widths[i]))
+      .collect();
+    println!("{}", cols.join("  ").trim_end());
+  }
+}
+
+fn print_response(response: &str, json: bool) {
+  if let Some(err) = response.strip_prefix("error: ") {
+    if json {
+      println!("{}", serde_json::json!({ "error": err }));
+      std::process::exit(1);
+    }
+    die(err);
+  }
+
+  if json {
+    if response == "ok" {
+      println!("{}", serde_json::json!({ "ok": true }));
+    } else {
+      println!("{}", response);
+    }
+  } else if response != "ok" {
+    println!("{}", response);
+  }
+}
+
+fn wait_running(socket: &str, timeout: Duration, json: bool) {
+  let deadline = Instant::now() + timeout;
+
+  loop {
+    // orderly may not have created its socket yet, so connection errors are retried.
+    if let Ok(response) = send_command(socket, "status") {
+      if let Ok(status) = serde_json::from_str::<serde_json::Value>(&response) {
+        if status["state"] == "running" {
+          if json {
+            println!("{}", response);
+          }
+          return;
+        }
+      }
+    }
+
+    if Instant::now() >= deadline {
+      die("timed out waiting for orderly to be running.");
+    }
+
+    std::thread::sleep(Duration::from_millis(200));
+  }
+}
+
+fn main() {
+  let args: Vec<String> = std::env::args().collect();
+  let mut arg_idx = 1;
+  let mut socket = std::env::var("ORDERLY_CONTROL_SOCKET").ok();
+  let mut json = false;
+  let mut timeout = Duration::from_secs(120);
+
+  while arg_idx < args.len() {
+    match args[arg_idx].as_ref() {
+      "-h" | "-help" | "--help" => usage(),
+      "-socket" => {
+        socket = Some(
+          args
+            .get(arg_idx + 1)
+            .unwrap_or_else(|| die("-socket expected an argument."))
+            .clone(),
+        );
+        arg_idx += 2;
+      }
+      "-timeout" => {
+        let arg = args
+          .get(arg_idx + 1)
+          .unwrap_or_else(|| die("-timeout expects a number."));
+        let secs = arg
+          .parse::<f64>()
+          .unwrap_or_else(|_e| die(format!("{} is not a valid f64.", arg).as_ref()));
+        timeout = Duration::from_millis((secs * 1000.0) as u64);
+        arg_idx += 2;
+      }
+      "-json" => {
+        json = true;
+        arg_idx += 1;
+      }
+      _ => break,
+    }
+  }
+
+  let socket =
+    socket.unwrap_or_else(|| die("no control socket, use -socket or ORDERLY_CONTROL_SOCKET."));
+
+  let command: Vec<&str> = args[arg_idx..].iter().map(|a| a.as_str()).collect();
+
+  let request = match command.as_slice() {
+    ["status"] => String::from("status"),
+    ["restart", name] => format!("restart {}", name),
+    ["restart-all"] => String::from("restart-all"),
+    ["reload"] => String::from("reload"),
+    ["stop"] => String::from("shutdown"),
+    ["terminate"] => String::from("terminate"),
+    ["logs", name] => format!("logs {}", name),
+    ["wait-running"] => {
+      wait_running(&socket, timeout, json);
+      return;
+    }
+    [] => die("expected a command, see -help."),
+    _ => die(format!("invalid command '{}', see -help.", command.join(" ")).as_ref()),
+  };
+
+  let response = send_command(&socket, &request)
+    .unwrap_or_else(|e| die(format!("unable to connect to {}: {}.", socket, e).as_ref()));
+
+  match command.as_slice() {
+    ["status"] if !json && !response.starts_with("error: ") => {
+      match serde_json::from_str::<serde_json::Value>(&response) {
+        Ok(status) => print_status(&status),
+        Err(e) => die(format!("invalid status response: {}.", e).as_ref()),
+      }
+    }
+    _ => print_response(&response, json),
+  }
+}
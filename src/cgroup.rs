@@ -0,0 +1,173 @@
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+// A cgroup v2 cgroup holding every process started by a proc's run
+// command. Unlike a process group, a process cannot leave its cgroup on
+// its own, so killing the cgroup is guaranteed to kill the whole tree.
+pub struct Cgroup {
+  path: std::path::PathBuf,
+  procs_file: std::fs::File,
+}
+
+impl Cgroup {
+  pub fn create(parent: &str, name: &str) -> Result<Cgroup, std::io::Error> {
+    let parent = std::path::Path::new(parent);
+    if !parent.join("cgroup.controllers").exists() {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("{} is not a cgroup v2 directory", parent.display()),
+      ));
+    }
+
+    let path = parent.join(name);
+    match std::fs::create_dir(&path) {
+      Ok(()) => (),
+      Err(ref err) if err.kind() == std::io::ErrorKind::AlreadyExists => (),
+      Err(err) => return Err(err),
+    }
+
+    let procs_file = std::fs::OpenOptions::new()
+      .write(true)
+      .open(path.join("cgroup.procs"))?;
+
+    Ok(Cgroup { path, procs_file })
+  }
+
+  // Writing "0" to this fd moves the writer into the cgroup, it is
+  // used between fork and exec so the child starts inside the cgroup.
+  pub fn procs_fd(&self) -> i32 {
+    self.procs_file.as_raw_fd()
+  }
+
+  // Controllers must be enabled in the parent before a child cgroup can
+  // set limits with them.
+  pub fn enable_controller(&self, controller: &str) -> Result<(), std::io::Error> {
+    let parent = match self.path.parent() {
+      Some(parent) => parent,
+      None => return Ok(()),
+    };
+    let enabled = std::fs::read_to_string(parent.join("cgroup.subtree_control"))?;
+    if enabled.split_whitespace().any(|c| c == controller) {
+      return Ok(());
+    }
+    std::fs::OpenOptions::new()
+      .write(true)
+      .open(parent.join("cgroup.subtree_control"))?
+      .write_all(format!("+{}", controller).as_bytes())
+  }
+
+  pub fn set_memory_max(&self, bytes: u64) -> Result<(), std::io::Error> {
+    self.enable_controller("memory")?;
+    self.write("memory.max", &bytes.to_string())?;
+    // An OOM kill takes down the whole cgroup, rather than leaving a
+    // service running with a random process missing.
+    self.write("memory.oom.group", "1")
+  }
+
+  pub fn set_cpu_max(&self, cpus: f64) -> Result<(), std::io::Error> {
+    let period: u64 = 100_000;
+    self.enable_controller("cpu")?;
+    self.write(
+      "cpu.max",
+      &format!("{} {}", (cpus * period as f64) as u64, period),
+    )
+  }
+
+  pub fn set_pids_max(&self, pids: u64) -> Result<(), std::io::Error> {
+    self.enable_controller("pids")?;
+    self.write("pids.max", &pids.to_string())
+  }
+
+  // The number of processes in the cgroup killed by the OOM killer.
+  pub fn oom_kills(&self) -> u64 {
+    let events = std::fs::read_to_string(self.path.join("memory.events")).unwrap_or_default();
+    events
+      .lines()
+      .find_map(|l| l.strip_prefix("oom_kill "))
+      .and_then(|n| n.trim().parse().ok())
+      .unwrap_or(0)
+  }
+
+  pub fn path(&self) -> &std::path::Path {
+    &self.path
+  }
+
+  pub fn pids(&self) -> Result<Vec<i32>, std::io::Error> {
+    pids(&self.path)
+  }
+
+  fn write(&self, file: &str, value: &str) -> Result<(), std::io::Error> {
+    std::fs::OpenOptions::new()
+      .write(true)
+      .open(self.path.join(file))?
+      .write_all(value.as_bytes())
+  }
+
+  fn event(&self, key: &str) -> Result<bool, std::io::Error> {
+    let events = std::fs::read_to_string(self.path.join("cgroup.events"))?;
+    Ok(events.lines().any(|l| l == format!("{} 1", key)))
+  }
+
+  fn wait_event(
+    &self,
+    key: &str,
+    want: bool,
+    deadline: Instant,
+    wait: &mut impl FnMut(Duration),
+  ) -> Result<bool, std::io::Error> {
+    loop {
+      if self.event(key)? == want {
+        return Ok(true);
+      }
+      if Instant::now() >= deadline {
+        return Ok(false);
+      }
+      wait(Duration::from_millis(10));
+    }
+  }
+
+  // SIGKILL everything in the cgroup and wait for it to be empty.
+  // Returns false if the cgroup is still populated at the deadline.
+  // Events are polled, wait is called to sleep between polls.
+  pub fn kill(
+    &self,
+    deadline: Instant,
+    mut wait: impl FnMut(Duration),
+  ) -> Result<bool, std::io::Error> {
+    if !self.event("populated")? {
+      return Ok(true);
+    }
+
+    if self.path.join("cgroup.kill").exists() {
+      self.write("cgroup.kill", "1")?;
+    } else {
+      // Before cgroup.kill, freezing first stops processes forking
+      // faster than we can kill them.
+      self.write("cgroup.freeze", "1")?;
+      self.wait_event("frozen", true, deadline, &mut wait)?;
+      for pid in self.pids()? {
+        unsafe { libc::kill(pid, libc::SIGKILL) };
+      }
+      self.write("cgroup.freeze", "0")?;
+    }
+
+    self.wait_event("populated", false, deadline, &mut wait)
+  }
+
+  pub fn remove(&self) -> Result<(), std::io::Error> {
+    std::fs::remove_dir(&self.path)
+  }
+}
+
+// The processes in the cgroup at path, for threads that only have the
+// path of a cgroup.
+pub fn pids(path: &std::path::Path) -> Result<Vec<i32>, std::io::Error> {
+  let procs = std::fs::read_to_string(path.join("cgroup.procs"))?;
+  Ok(
+    procs
+      .lines()
+      .filter_map(|l| l.trim().parse().ok())
+      .collect(),
+  )
+}
@@ -0,0 +1,213 @@
+use crate::specs;
+use std::io::{BufRead, Read, Write};
+use std::net::ToSocketAddrs;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Built-in checks run inside orderly instead of forking a check
+// command. Each returns a description of what went wrong on failure.
+// Relative paths are resolved against dir, the directory the proc's
+// commands run in.
+
+pub fn run(
+  check: &specs::BuiltinCheck,
+  dir: Option<&str>,
+  deadline: Option<Instant>,
+) -> Result<(), String> {
+  match check {
+    specs::BuiltinCheck::Tcp(address) => connect_tcp(address, deadline).map(|_| ()),
+    specs::BuiltinCheck::Http { url, status } => http_get(url, *status, deadline),
+    specs::BuiltinCheck::Unix(path) => connect_unix(&resolve_path(dir, path), deadline),
+    specs::BuiltinCheck::File {
+      path,
+      max_age_seconds,
+    } => check_file(&resolve_path(dir, path), *max_age_seconds),
+  }
+}
+
+fn resolve_path(dir: Option<&str>, path: &str) -> PathBuf {
+  match dir {
+    Some(dir) => Path::new(dir).join(path),
+    None => PathBuf::from(path),
+  }
+}
+
+// Only plain http is supported, returns the address to connect to, the
+// host header and the request path.
+pub fn parse_http_url(url: &str) -> Result<(String, String, String), String> {
+  let rest = url
+    .strip_prefix("http://")
+    .ok_or_else(|| format!("{} is not an http:// url", url))?;
+  let (host, path) = match rest.find('/') {
+    Some(i) => (&rest[..i], &rest[i..]),
+    None => (rest, "/"),
+  };
+  if host.is_empty() {
+    return Err(format!("{} has no host", url));
+  }
+  let has_port = match host.rsplit_once(':') {
+    Some((_, port)) => !host.ends_with(']') && port.parse::<u16>().is_ok(),
+    None => false,
+  };
+  let address = if has_port {
+    host.to_string()
+  } else {
+    format!("{}:80", host)
+  };
+  Ok((address, host.to_string(), path.to_string()))
+}
+
+fn remaining(deadline: Option<Instant>) -> Result<Option<Duration>, String> {
+  match deadline {
+    Some(deadline) => {
+      let now = Instant::now();
+      if now >= deadline {
+        return Err(String::from("timed out"));
+      }
+      Ok(Some(deadline - now))
+    }
+    None => Ok(None),
+  }
+}
+
+// Name lookups can't be given a timeout, so they run on their own
+// thread that is abandoned if the deadline passes first.
+fn resolve(address: &str, deadline: Option<Instant>) -> Result<Vec<std::net::SocketAddr>, String> {
+  let (tx, rx) = crossbeam_channel::bounded(1);
+  let lookup = address.to_string();
+  std::thread::spawn(move || {
+    let _ = tx.send(lookup.to_socket_addrs().map(|addrs| addrs.collect()));
+  });
+  let result = match remaining(deadline)? {
+    Some(timeout) => rx
+      .recv_timeout(timeout)
+      .map_err(|_| String::from("timed out"))?,
+    None => rx.recv().map_err(|_| String::from("lookup failed"))?,
+  };
+  result.map_err(|e| format!("unable to resolve {}: {}", address, e))
+}
+
+fn connect_tcp(address: &str, deadline: Option<Instant>) -> Result<std::net::TcpStream, String> {
+  let addrs = resolve(address, deadline)?;
+  let mut err = format!("{} did not resolve to any address", address);
+  for addr in addrs {
+    let result = match remaining(deadline)? {
+      Some(timeout) => std::net::TcpStream::connect_timeout(&addr, timeout),
+      None => std::net::TcpStream::connect(addr),
+    };
+    match result {
+      Ok(stream) => return Ok(stream),
+      Err(e) => err = format!("unable to connect to {}: {}", addr, e),
+    }
+  }
+  Err(err)
+}
+
+// A connect to a unix socket whose listen backlog is full blocks until
+// there is room, a non-blocking connect fails with EAGAIN instead and
+// is retried until the deadline.
+fn connect_unix(path: &Path, deadline: Option<Instant>) -> Result<(), String> {
+  let io_err = |e: std::io::Error| format!("unable to connect to {}: {}", path.display(), e);
+  let mut addr: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+  addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+  let bytes = path.as_os_str().as_bytes();
+  if bytes.len() >= addr.sun_path.len() {
+    return Err(format!("{} is too long for a unix socket", path.display()));
+  }
+  for (dst, src) in addr.sun_path.iter_mut().zip(bytes) {
+    *dst = *src as libc::c_char;
+  }
+
+  let fd = unsafe {
+    libc::socket(
+      libc::AF_UNIX,
+      libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+      0,
+    )
+  };
+  if fd < 0 {
+    return Err(io_err(std::io::Error::last_os_error()));
+  }
+  let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+  loop {
+    let rc = unsafe {
+      libc::connect(
+        fd.as_raw_fd(),
+        &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+        std::mem::size_of::<libc::sockaddr_un>() as libc::socklen_t,
+      )
+    };
+    if rc == 0 {
+      return Ok(());
+    }
+    let e = std::io::Error::last_os_error();
+    if e.raw_os_error() != Some(libc::EAGAIN) {
+      return Err(io_err(e));
+    }
+    let retry = Duration::from_millis(10);
+    let wait = remaining(deadline)
+      .map_err(|e| format!("unable to connect to {}: {}", path.display(), e))?
+      .map_or(retry, |left| left.min(retry));
+    std::thread::sleep(wait);
+  }
+}
+
+fn http_get(url: &str, want_status: u64, deadline: Option<Instant>) -> Result<(), String> {
+  let (address, host, path) = parse_http_url(url)?;
+  let mut stream = connect_tcp(&address, deadline)?;
+  let io_err = |e: std::io::Error| format!("{}: {}", url, e);
+
+  stream
+    .set_write_timeout(remaining(deadline)?)
+    .map_err(io_err)?;
+  write!(
+    stream,
+    "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: orderly\r\nConnection: close\r\n\r\n",
+    path, host
+  )
+  .map_err(io_err)?;
+
+  stream
+    .set_read_timeout(remaining(deadline)?)
+    .map_err(io_err)?;
+  let mut status_line = String::new();
+  std::io::BufReader::new(stream.take(1024))
+    .read_line(&mut status_line)
+    .map_err(io_err)?;
+
+  // HTTP/1.1 200 OK
+  let status = status_line
+    .split_whitespace()
+    .nth(1)
+    .and_then(|s| s.parse::<u64>().ok())
+    .ok_or_else(|| format!("{}: invalid response '{}'", url, status_line.trim_end()))?;
+  if status != want_status {
+    return Err(format!(
+      "{}: status {}, expected {}",
+      url, status, want_status
+    ));
+  }
+  Ok(())
+}
+
+fn check_file(path: &Path, max_age_seconds: Option<f64>) -> Result<(), String> {
+  let io_err = |e: std::io::Error| format!("{}: {}", path.display(), e);
+  let metadata = std::fs::metadata(path).map_err(io_err)?;
+  if let Some(max_age_seconds) = max_age_seconds {
+    let modified = metadata.modified().map_err(io_err)?;
+    // A file modified in the future counts as fresh.
+    let age = modified.elapsed().unwrap_or_default().as_secs_f64();
+    if age > max_age_seconds {
+      return Err(format!(
+        "{} was last modified {:.1}s ago, more than {}s",
+        path.display(),
+        age,
+        max_age_seconds
+      ));
+    }
+  }
+  Ok(())
+}
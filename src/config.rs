@@ -0,0 +1,452 @@
+use crate::specs;
+use serde::Deserialize;
+
+#[derive(Debug)]
+pub enum ConfigError {
+  IOError(std::io::Error),
+  ParseError(toml::de::Error),
+}
+
+impl std::fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      ConfigError::IOError(e) => write!(f, "{}", e),
+      ConfigError::ParseError(e) => write!(f, "{}", e),
+    }
+  }
+}
+
+// Config keys mirror the command line flags, so '-check-delay 5'
+// becomes 'check-delay = 5', and each '[[proc]]' table is equivalent
+// to a '--' separated set of process flags.
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SupervisorConfig {
+  max_restart_tokens: Option<f64>,
+  restart_tokens_per_second: Option<f64>,
+  #[serde(default, deserialize_with = "finite")]
+  restart_backoff: Option<f64>,
+  #[serde(default, deserialize_with = "finite")]
+  restart_backoff_max: Option<f64>,
+  #[serde(default, deserialize_with = "finite")]
+  restart_backoff_multiplier: Option<f64>,
+  #[serde(default, deserialize_with = "finite")]
+  restart_backoff_jitter: Option<f64>,
+  #[serde(default, deserialize_with = "finite")]
+  restart_backoff_reset: Option<f64>,
+  check_delay: Option<f64>,
+  restart_strategy: Option<specs::RestartStrategy>,
+  status_file: Option<String>,
+  status_format: Option<specs::StatusFormat>,
+  control_socket: Option<String>,
+  output: Option<specs::OutputMode>,
+  output_color: Option<specs::ColorMode>,
+  cgroup_parent: Option<String>,
+  #[serde(flatten)]
+  environment: EnvConfig,
+  #[serde(default, deserialize_with = "command")]
+  all_commands: Option<specs::Command>,
+  #[serde(default, deserialize_with = "command")]
+  start_complete: Option<specs::Command>,
+  start_complete_timeout: Option<f64>,
+  #[serde(default, deserialize_with = "command")]
+  on_restart: Option<specs::Command>,
+  on_restart_timeout: Option<f64>,
+  #[serde(default, deserialize_with = "command")]
+  on_failure: Option<specs::Command>,
+  on_failure_timeout: Option<f64>,
+  #[serde(default, rename = "proc")]
+  procs: Vec<ProcConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ProcConfig {
+  name: Option<String>,
+  #[serde(default, deserialize_with = "command")]
+  all_commands: Option<specs::Command>,
+  #[serde(default, deserialize_with = "command")]
+  run: Option<specs::Command>,
+  #[serde(default, deserialize_with = "command")]
+  wait_started: Option<specs::Command>,
+  wait_started_timeout: Option<f64>,
+  #[serde(default)]
+  notify: bool,
+  watchdog: Option<f64>,
+  notification_fd: Option<i32>,
+  #[serde(default, deserialize_with = "listen")]
+  listen: Vec<specs::ListenSpec>,
+  #[serde(default, deserialize_with = "command")]
+  check: Option<specs::Command>,
+  check_timeout: Option<f64>,
+  check_tcp: Option<String>,
+  check_http: Option<String>,
+  check_http_status: Option<u64>,
+  check_unix: Option<String>,
+  check_file: Option<String>,
+  check_file_max_age: Option<f64>,
+  #[serde(default, deserialize_with = "command")]
+  shutdown: Option<specs::Command>,
+  shutdown_timeout: Option<f64>,
+  terminate_timeout: Option<f64>,
+  #[serde(default, deserialize_with = "command")]
+  cleanup: Option<specs::Command>,
+  cleanup_timeout: Option<f64>,
+  log_file: Option<String>,
+  log_dir: Option<String>,
+  log_max_size: Option<u64>,
+  log_keep: Option<u64>,
+  memory_max: Option<u64>,
+  cpu_max: Option<f64>,
+  pids_max: Option<u64>,
+  #[serde(default, deserialize_with = "rlimit")]
+  rlimit_nofile: Option<u64>,
+  #[serde(default, deserialize_with = "rlimit")]
+  rlimit_core: Option<u64>,
+  #[serde(default, deserialize_with = "rlimit")]
+  rlimit_as: Option<u64>,
+  #[serde(default, deserialize_with = "rlimit")]
+  rlimit_nproc: Option<u64>,
+  nice: Option<i32>,
+  #[serde(default, deserialize_with = "ioprio")]
+  ioprio: Option<specs::IoPrio>,
+  oom_score_adj: Option<i32>,
+  #[serde(default, deserialize_with = "cpu_list")]
+  cpu_affinity: Option<Vec<usize>>,
+  user: Option<String>,
+  group: Option<String>,
+  groups: Option<Vec<String>>,
+  hook_user: Option<String>,
+  hook_group: Option<String>,
+  #[serde(flatten)]
+  environment: EnvConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EnvConfig {
+  dir: Option<String>,
+  #[serde(default)]
+  env: std::collections::BTreeMap<String, String>,
+  #[serde(default)]
+  env_file: Vec<String>,
+  #[serde(default)]
+  clear_env: bool,
+  #[serde(default)]
+  pass_env: Vec<String>,
+}
+
+// Values that use the same syntax as their command line flags.
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString {
+  Number(u64),
+  String(String),
+}
+
+fn rlimit<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<u64>, D::Error> {
+  match NumberOrString::deserialize(d)? {
+    NumberOrString::Number(n) => Ok(Some(n)),
+    NumberOrString::String(s) => specs::parse_rlimit(&s)
+      .map(Some)
+      .ok_or_else(|| serde::de::Error::custom(format!("{} is not a valid rlimit", s))),
+  }
+}
+
+// A string is a single program like the plain flags, an array is an
+// argument vector like the -argv flags.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrArray {
+  String(String),
+  Array(Vec<String>),
+}
+
+fn command<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<specs::Command>, D::Error> {
+  match StringOrArray::deserialize(d)? {
+    StringOrArray::String(s) => Ok(Some(vec![s])),
+    StringOrArray::Array(ref argv) if argv.is_empty() => {
+      Err(serde::de::Error::custom("a command must not be empty"))
+    }
+    StringOrArray::Array(argv) => Ok(Some(argv)),
+  }
+}
+
+fn ioprio<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<specs::IoPrio>, D::Error> {
+  let s = String::deserialize(d)?;
+  specs::IoPrio::parse(&s)
+    .map(Some)
+    .ok_or_else(|| serde::de::Error::custom(format!("{} is not a valid ioprio", s)))
+}
+
+fn listen<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Vec<specs::ListenSpec>, D::Error> {
+  Vec::<String>::deserialize(d)?
+    .iter()
+    .map(|s| {
+      specs::ListenSpec::parse(s)
+        .ok_or_else(|| serde::de::Error::custom(format!("{} is not a valid listen address", s)))
+    })
+    .collect()
+}
+
+fn finite<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<f64>, D::Error> {
+  let v = f64::deserialize(d)?;
+  if v.is_finite() {
+    Ok(Some(v))
+  } else {
+    Err(serde::de::Error::custom(format!(
+      "{} is not a finite number",
+      v
+    )))
+  }
+}
+
+fn cpu_list<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<Vec<usize>>, D::Error> {
+  let s = String::deserialize(d)?;
+  specs::parse_cpu_list(&s)
+    .map(Some)
+    .ok_or_else(|| serde::de::Error::custom(format!("{} is not a valid cpu list", s)))
+}
+
+pub fn load(path: &str) -> Result<SupervisorConfig, ConfigError> {
+  let data = std::fs::read_to_string(path).map_err(ConfigError::IOError)?;
+  toml::from_str(&data).map_err(ConfigError::ParseError)
+}
+
+impl SupervisorConfig {
+  pub fn apply(&self, b: &mut specs::SupervisorSpecBuilder) {
+    if let Some(ref all) = self.all_commands {
+      b.set_start_complete(all.clone());
+      b.set_restart(all.clone());
+      b.set_failure(all.clone());
+    }
+    if let Some(v) = self.max_restart_tokens {
+      b.set_max_restart_tokens(v);
+    }
+    if let Some(v) = self.restart_tokens_per_second {
+      b.set_restart_tokens_per_second(v);
+    }
+    if let Some(v) = self.restart_backoff {
+      b.set_restart_backoff_seconds(v);
+    }
+    if let Some(v) = self.restart_backoff_max {
+      b.set_restart_backoff_max_seconds(v);
+    }
+    if let Some(v) = self.restart_backoff_multiplier {
+      b.set_restart_backoff_multiplier(v);
+    }
+    if let Some(v) = self.restart_backoff_jitter {
+      b.set_restart_backoff_jitter(v);
+    }
+    if let Some(v) = self.restart_backoff_reset {
+      b.set_restart_backoff_reset_seconds(v);
+    }
+    if let Some(v) = self.check_delay {
+      b.set_check_delay_seconds(v);
+    }
+    if let Some(v) = self.restart_strategy {
+      b.set_restart_strategy(v);
+    }
+    if let Some(ref v) = self.status_file {
+      b.set_status_file(v.clone());
+    }
+    if let Some(v) = self.status_format {
+      b.set_status_format(v);
+    }
+    if let Some(ref v) = self.control_socket {
+      b.set_control_socket(v.clone());
+    }
+    if let Some(v) = self.output {
+      b.set_output(v);
+    }
+    if let Some(v) = self.output_color {
+      b.set_output_color(v);
+    }
+    if let Some(ref v) = self.cgroup_parent {
+      b.set_cgroup_parent(v.clone());
+    }
+    if let Some(ref v) = self.start_complete {
+      b.set_start_complete(v.clone());
+    }
+    if let Some(v) = self.start_complete_timeout {
+      b.set_start_complete_timeout(v);
+    }
+    if let Some(ref v) = self.on_restart {
+      b.set_restart(v.clone());
+    }
+    if let Some(v) = self.on_restart_timeout {
+      b.set_restart_timeout(v);
+    }
+    if let Some(ref v) = self.on_failure {
+      b.set_failure(v.clone());
+    }
+    if let Some(v) = self.on_failure_timeout {
+      b.set_failure_timeout(v);
+    }
+    self.environment.apply(b.environment());
+  }
+
+  pub fn proc_spec_builders(&self) -> Vec<specs::ProcSpecBuilder> {
+    self.procs.iter().map(|p| p.to_builder()).collect()
+  }
+}
+
+impl ProcConfig {
+  fn to_builder(&self) -> specs::ProcSpecBuilder {
+    let mut b = specs::ProcSpecBuilder::new();
+    if let Some(ref all) = self.all_commands {
+      b.set_run(all.clone());
+      b.set_check(all.clone());
+      b.set_wait_started(all.clone());
+      b.set_shutdown(all.clone());
+      b.set_cleanup(all.clone());
+    }
+    if let Some(ref v) = self.name {
+      b.set_name(v.clone());
+    }
+    if let Some(ref v) = self.run {
+      b.set_run(v.clone());
+    }
+    if let Some(ref v) = self.wait_started {
+      b.set_wait_started(v.clone());
+    }
+    if let Some(v) = self.wait_started_timeout {
+      b.set_wait_started_timeout_seconds(v);
+    }
+    if self.notify {
+      b.set_notify(true);
+    }
+    if let Some(v) = self.watchdog {
+      b.set_watchdog_seconds(v);
+    }
+    if let Some(v) = self.notification_fd {
+      b.set_notification_fd(v);
+    }
+    for v in self.listen.iter() {
+      b.add_listen(v.clone());
+    }
+    if let Some(ref v) = self.check {
+      b.set_check(v.clone());
+    }
+    if let Some(v) = self.check_timeout {
+      b.set_check_timeout_seconds(v);
+    }
+    if let Some(ref v) = self.check_tcp {
+      b.set_check_tcp(v.clone());
+    }
+    if let Some(ref v) = self.check_http {
+      b.set_check_http(v.clone());
+    }
+    if let Some(v) = self.check_http_status {
+      b.set_check_http_status(v);
+    }
+    if let Some(ref v) = self.check_unix {
+      b.set_check_unix(v.clone());
+    }
+    if let Some(ref v) = self.check_file {
+      b.set_check_file(v.clone());
+    }
+    if let Some(v) = self.check_file_max_age {
+      b.set_check_file_max_age_seconds(v);
+    }
+    if let Some(ref v) = self.shutdown {
+      b.set_shutdown(v.clone());
+    }
+    if let Some(v) = self.shutdown_timeout {
+      b.set_shutdown_timeout_seconds(v);
+    }
+    if let Some(v) = self.terminate_timeout {
+      b.set_terminate_timeout_seconds(v);
+    }
+    if let Some(ref v) = self.cleanup {
+      b.set_cleanup(v.clone());
+    }
+    if let Some(v) = self.cleanup_timeout {
+      b.set_cleanup_timeout_seconds(v);
+    }
+    if let Some(ref v) = self.log_file {
+      b.set_log_file(v.clone());
+    }
+    if let Some(ref v) = self.log_dir {
+      b.set_log_dir(v.clone());
+    }
+    if let Some(v) = self.log_max_size {
+      b.set_log_max_bytes(v);
+    }
+    if let Some(v) = self.log_keep {
+      b.set_log_keep(v);
+    }
+    if let Some(v) = self.memory_max {
+      b.set_memory_max(v);
+    }
+    if let Some(v) = self.cpu_max {
+      b.set_cpu_max(v);
+    }
+    if let Some(v) = self.pids_max {
+      b.set_pids_max(v);
+    }
+    if let Some(v) = self.rlimit_nofile {
+      b.set_rlimit_nofile(v);
+    }
+    if let Some(v) = self.rlimit_core {
+      b.set_rlimit_core(v);
+    }
+    if let Some(v) = self.rlimit_as {
+      b.set_rlimit_as(v);
+    }
+    if let Some(v) = self.rlimit_nproc {
+      b.set_rlimit_nproc(v);
+    }
+    if let Some(v) = self.nice {
+      b.set_nice(v);
+    }
+    if let Some(v) = self.ioprio {
+      b.set_ioprio(v);
+    }
+    if let Some(v) = self.oom_score_adj {
+      b.set_oom_score_adj(v);
+    }
+    if let Some(ref v) = self.cpu_affinity {
+      b.set_cpu_affinity(v.clone());
+    }
+    if let Some(ref v) = self.user {
+      b.set_user(v.clone());
+    }
+    if let Some(ref v) = self.group {
+      b.set_group(v.clone());
+    }
+    if let Some(ref v) = self.groups {
+      b.set_groups(v.clone());
+    }
+    if let Some(ref v) = self.hook_user {
+      b.set_hook_user(v.clone());
+    }
+    if let Some(ref v) = self.hook_group {
+      b.set_hook_group(v.clone());
+    }
+    self.environment.apply(b.environment());
+    b
+  }
+}
+
+impl EnvConfig {
+  fn apply(&self, b: &mut specs::EnvSpecBuilder) {
+    if let Some(ref v) = self.dir {
+      b.set_dir(v.clone());
+    }
+    for v in self.env_file.iter() {
+      b.add_env_file(v.clone());
+    }
+    for (k, v) in self.env.iter() {
+      b.add_env(k.clone(), v.clone());
+    }
+    if self.clear_env {
+      b.set_clear_env(true);
+    }
+    for v in self.pass_env.iter() {
+      b.add_pass_env(v.clone());
+    }
+  }
+}
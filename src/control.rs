@@ -0,0 +1,80 @@
+use crate::Signal;
+use std::io::{BufRead, Write};
+use std::os::unix::net::UnixStream;
+
+// The control protocol is one command line per connection, orderly
+// writes a response and closes the connection. Responses are either
+// 'ok', 'error: ...' or a json document for commands that return data.
+
+pub fn listen(path: &str, sigtx: crossbeam_channel::Sender<Signal>) -> Result<(), std::io::Error> {
+  let listener = crate::listen::bind_unix(path)?;
+
+  std::thread::spawn(move || {
+    for conn in listener.incoming() {
+      match conn {
+        Ok(conn) => {
+          let sigtx = sigtx.clone();
+          std::thread::spawn(move || {
+            if let Err(err) = handle_conn(conn, sigtx) {
+              log::warn!("control connection error: {}.", err);
+            }
+          });
+        }
+        Err(err) => log::warn!("unable to accept control connection: {}.", err),
+      }
+    }
+  });
+
+  Ok(())
+}
+
+fn request(
+  sigtx: &crossbeam_channel::Sender<Signal>,
+  mk_signal: impl FnOnce(crossbeam_channel::Sender<String>) -> Signal,
+) -> String {
+  let (reply_tx, reply_rx) = crossbeam_channel::bounded(1);
+  if sigtx.send(mk_signal(reply_tx)).is_err() {
+    return String::from("error: supervisor is not running");
+  }
+  match reply_rx.recv() {
+    Ok(reply) => reply,
+    Err(_) => String::from("error: supervisor exited before responding"),
+  }
+}
+
+fn handle_conn(
+  conn: UnixStream,
+  sigtx: crossbeam_channel::Sender<Signal>,
+) -> Result<(), std::io::Error> {
+  let mut line = String::new();
+  std::io::BufReader::new(&conn).read_line(&mut line)?;
+  let words: Vec<&str> = line.split_whitespace().collect();
+
+  let response = match words.as_slice() {
+    ["status"] => request(&sigtx, Signal::Status),
+    ["restart", name] => {
+      let name = name.to_string();
+      request(&sigtx, move |reply| Signal::Restart(name, reply))
+    }
+    ["restart-all"] => request(&sigtx, Signal::RestartAll),
+    ["reload"] => request(&sigtx, |reply| Signal::Reload(Some(reply))),
+    ["logs", name] => {
+      let name = name.to_string();
+      request(&sigtx, move |reply| Signal::Logs(name, reply))
+    }
+    ["shutdown"] => match sigtx.send(Signal::Shutdown) {
+      Ok(()) => String::from("ok"),
+      Err(_) => String::from("error: supervisor is not running"),
+    },
+    ["terminate"] => match sigtx.send(Signal::Terminate(None)) {
+      Ok(()) => String::from("ok"),
+      Err(_) => String::from("error: supervisor is not running"),
+    },
+    _ => format!("error: unknown command '{}'", line.trim()),
+  };
+
+  let mut conn = conn;
+  conn.write_all(response.as_bytes())?;
+  conn.write_all(b"\n")?;
+  Ok(())
+}
@@ -0,0 +1,81 @@
+// Parses env files in the common dotenv format:
+//
+//   # A comment.
+//   export KEY=value
+//   KEY="a \"double\" quoted value\n"
+//   KEY='a single quoted value'
+//   KEY=value # A trailing comment.
+//
+// Values are not interpolated, and quoted values cannot span lines.
+
+pub fn load(path: &str) -> Result<Vec<(String, String)>, String> {
+  let data = std::fs::read_to_string(path)
+    .map_err(|e| format!("unable to read env file {}: {}", path, e))?;
+  let mut vars = vec![];
+  for (n, line) in data.lines().enumerate() {
+    if let Some(v) = parse_line(line).map_err(|e| format!("{} line {}: {}", path, n + 1, e))? {
+      vars.push(v);
+    }
+  }
+  Ok(vars)
+}
+
+fn parse_line(line: &str) -> Result<Option<(String, String)>, &'static str> {
+  let line = line.trim();
+  if line.is_empty() || line.starts_with('#') {
+    return Ok(None);
+  }
+  let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+
+  let (key, value) = match line.split_once('=') {
+    Some((key, value)) => (key.trim(), value.trim()),
+    None => return Err("expected KEY=VALUE"),
+  };
+  if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+    return Err("invalid variable name");
+  }
+
+  let value = if let Some(rest) = value.strip_prefix('"') {
+    let mut out = String::new();
+    let mut chars = rest.chars();
+    loop {
+      match chars.next() {
+        Some('"') => break,
+        Some('\\') => match chars.next() {
+          Some('n') => out.push('\n'),
+          Some('t') => out.push('\t'),
+          Some(c) => out.push(c),
+          None => return Err("unterminated double quote"),
+        },
+        Some(c) => out.push(c),
+        None => return Err("unterminated double quote"),
+      }
+    }
+    check_trailing(chars.as_str())?;
+    out
+  } else if let Some(rest) = value.strip_prefix('\'') {
+    match rest.split_once('\'') {
+      Some((quoted, trailing)) => {
+        check_trailing(trailing)?;
+        quoted.to_string()
+      }
+      None => return Err("unterminated single quote"),
+    }
+  } else {
+    match value.find(" #") {
+      Some(i) => value[..i].trim_end().to_string(),
+      None => value.to_string(),
+    }
+  };
+
+  Ok(Some((key.to_string(), value)))
+}
+
+fn check_trailing(s: &str) -> Result<(), &'static str> {
+  let s = s.trim();
+  if s.is_empty() || s.starts_with('#') {
+    Ok(())
+  } else {
+    Err("unexpected characters after quoted value")
+  }
+}
@@ -0,0 +1,101 @@
+use std::ffi::{CString, OsString};
+use std::os::unix::ffi::OsStrExt;
+
+extern "C" {
+  static mut environ: *const *const libc::c_char;
+}
+
+// Some variables passed to the run command hold its own pid, which is
+// only known after the fork. Everything is allocated up front with a
+// fixed width slot for each pid, which is filled in between fork and
+// exec, where allocating is not safe.
+pub struct Exec {
+  argv: Vec<CString>,
+  env: Vec<Vec<u8>>,
+  pid_slots: Vec<(usize, usize)>,
+  argv_ptrs: Vec<*const libc::c_char>,
+  env_ptrs: Vec<*const libc::c_char>,
+}
+
+// The pointers only refer to buffers owned by the Exec.
+unsafe impl Send for Exec {}
+unsafe impl Sync for Exec {}
+
+// Enough digits for any pid_t.
+const PID_WIDTH: usize = 10;
+
+pub fn prepare(
+  command: &[String],
+  env: Vec<(OsString, OsString)>,
+  pid_vars: &[&str],
+) -> Result<Exec, std::io::Error> {
+  let invalid = || std::io::Error::from(std::io::ErrorKind::InvalidInput);
+  let argv = command
+    .iter()
+    .map(|a| CString::new(a.as_bytes()).map_err(|_| invalid()))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let mut vars = vec![];
+  for (k, v) in env.iter() {
+    if pid_vars.iter().any(|p| k == p) {
+      continue;
+    }
+    let mut var = k.as_bytes().to_vec();
+    var.push(b'=');
+    var.extend_from_slice(v.as_bytes());
+    if var.contains(&0) {
+      return Err(invalid());
+    }
+    var.push(0);
+    vars.push(var);
+  }
+  let mut pid_slots = vec![];
+  for name in pid_vars {
+    let mut var = format!("{}=", name).into_bytes();
+    pid_slots.push((vars.len(), var.len()));
+    var.resize(var.len() + PID_WIDTH + 1, 0);
+    vars.push(var);
+  }
+
+  let mut argv_ptrs: Vec<*const libc::c_char> = argv.iter().map(|a| a.as_ptr()).collect();
+  argv_ptrs.push(std::ptr::null());
+  let mut env_ptrs: Vec<*const libc::c_char> = vars
+    .iter()
+    .map(|v| v.as_ptr() as *const libc::c_char)
+    .collect();
+  env_ptrs.push(std::ptr::null());
+
+  Ok(Exec {
+    argv,
+    env: vars,
+    pid_slots,
+    argv_ptrs,
+    env_ptrs,
+  })
+}
+
+impl Exec {
+  // Only returns on failure. Exec searches PATH the same way as
+  // std::process::Command, using the new environment.
+  pub unsafe fn exec(&mut self) -> std::io::Error {
+    let mut digits = [0u8; PID_WIDTH];
+    let mut pid = libc::getpid() as u32;
+    let mut n = 0;
+    loop {
+      digits[PID_WIDTH - 1 - n] = b'0' + (pid % 10) as u8;
+      n += 1;
+      pid /= 10;
+      if pid == 0 || n == PID_WIDTH {
+        break;
+      }
+    }
+    for &(var, offset) in self.pid_slots.iter() {
+      let slot = &mut self.env[var][offset..];
+      slot[..n].copy_from_slice(&digits[PID_WIDTH - n..]);
+      slot[n] = 0;
+    }
+    environ = self.env_ptrs.as_ptr();
+    libc::execvp(self.argv[0].as_ptr(), self.argv_ptrs.as_ptr());
+    std::io::Error::last_os_error()
+  }
+}
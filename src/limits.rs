@@ -0,0 +1,102 @@
+use crate::specs;
+
+// Process attributes for a run command. Everything that allocates is
+// done in prepare, apply runs between fork and exec where only async
+// signal safe calls are allowed.
+pub struct Limits {
+  rlimits: Vec<(i32, u64)>,
+  nice: Option<i32>,
+  ioprio: Option<i32>,
+  oom_score_adj: Option<(std::ffi::CString, Vec<u8>)>,
+  cpu_affinity: Option<libc::cpu_set_t>,
+}
+
+const IOPRIO_WHO_PROCESS: i32 = 1;
+const IOPRIO_CLASS_SHIFT: i32 = 13;
+
+impl Limits {
+  pub fn prepare(s: &specs::ProcSpec) -> Limits {
+    let rlimits = [
+      (libc::RLIMIT_NOFILE as i32, s.rlimit_nofile),
+      (libc::RLIMIT_CORE as i32, s.rlimit_core),
+      (libc::RLIMIT_AS as i32, s.rlimit_as),
+      (libc::RLIMIT_NPROC as i32, s.rlimit_nproc),
+    ]
+    .iter()
+    .filter_map(|(resource, limit)| limit.map(|limit| (*resource, limit)))
+    .collect();
+
+    let ioprio = s.ioprio.map(|ioprio| match ioprio {
+      specs::IoPrio::RealTime(level) => (1 << IOPRIO_CLASS_SHIFT) | level as i32,
+      specs::IoPrio::BestEffort(level) => (2 << IOPRIO_CLASS_SHIFT) | level as i32,
+      specs::IoPrio::Idle => 3 << IOPRIO_CLASS_SHIFT,
+    });
+
+    let oom_score_adj = s.oom_score_adj.map(|adj| {
+      (
+        std::ffi::CString::new("/proc/self/oom_score_adj").unwrap(),
+        adj.to_string().into_bytes(),
+      )
+    });
+
+    let cpu_affinity = s.cpu_affinity.as_ref().map(|cpus| {
+      let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+      for cpu in cpus.iter() {
+        unsafe { libc::CPU_SET(*cpu, &mut set) };
+      }
+      set
+    });
+
+    Limits {
+      rlimits,
+      nice: s.nice,
+      ioprio,
+      oom_score_adj,
+      cpu_affinity,
+    }
+  }
+
+  pub unsafe fn apply(&self) -> Result<(), std::io::Error> {
+    for (resource, limit) in self.rlimits.iter() {
+      let rlim = libc::rlimit {
+        rlim_cur: *limit as libc::rlim_t,
+        rlim_max: *limit as libc::rlim_t,
+      };
+      if libc::setrlimit(*resource as _, &rlim) != 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+    }
+
+    if let Some(nice) = self.nice {
+      if libc::setpriority(libc::PRIO_PROCESS as _, 0, nice) != 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+    }
+
+    if let Some(ioprio) = self.ioprio {
+      if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) != 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+    }
+
+    if let Some((ref path, ref value)) = self.oom_score_adj {
+      let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+      if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+      let n = libc::write(fd, value.as_ptr() as *const libc::c_void, value.len());
+      libc::close(fd);
+      if n != value.len() as isize {
+        return Err(std::io::Error::last_os_error());
+      }
+    }
+
+    if let Some(ref set) = self.cpu_affinity {
+      if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), set) != 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,50 @@
+use crate::specs;
+use std::os::fd::{AsRawFd, OwnedFd};
+
+// A socket bound by orderly and passed to the run command with the
+// systemd LISTEN_FDS convention. Listeners are bound on the first start
+// and kept open across restarts, so connections queue up while the proc
+// restarts instead of being refused.
+pub struct Listener {
+  pub name: String,
+  fd: OwnedFd,
+  path: Option<String>,
+}
+
+pub fn bind(name: &str, address: &specs::ListenAddress) -> Result<Listener, std::io::Error> {
+  let (fd, path) = match address {
+    specs::ListenAddress::Tcp(address) => (std::net::TcpListener::bind(address)?.into(), None),
+    specs::ListenAddress::Unix(path) => (bind_unix(path)?.into(), Some(path.clone())),
+  };
+  Ok(Listener {
+    name: name.to_string(),
+    fd,
+    path,
+  })
+}
+
+// Binds a unix socket at path, for listeners and the control socket.
+pub fn bind_unix(path: &str) -> Result<std::os::unix::net::UnixListener, std::io::Error> {
+  // A stale socket from a previous run would make bind fail.
+  if let Ok(meta) = std::fs::symlink_metadata(path) {
+    use std::os::unix::fs::FileTypeExt;
+    if meta.file_type().is_socket() {
+      std::fs::remove_file(path)?;
+    }
+  }
+  std::os::unix::net::UnixListener::bind(path)
+}
+
+impl Listener {
+  pub fn fd(&self) -> i32 {
+    self.fd.as_raw_fd()
+  }
+}
+
+impl Drop for Listener {
+  fn drop(&mut self) {
+    if let Some(ref path) = self.path {
+      let _ = std::fs::remove_file(path);
+    }
+  }
+}
@@ -0,0 +1,139 @@
+use std::io::{Read, Seek, Write};
+use std::os::unix::io::FromRawFd;
+
+// A LogPipe is created once per process and lives as long as orderly,
+// each run of the process gets a copy of the write end as its stdout
+// and stderr. Keeping the pipe open across restarts means output
+// written by a dying process is never lost.
+pub struct LogPipe {
+  write_end: std::fs::File,
+}
+
+impl LogPipe {
+  pub fn stdio(&self) -> Result<(std::process::Stdio, std::process::Stdio), std::io::Error> {
+    Ok((
+      std::process::Stdio::from(self.write_end.try_clone()?),
+      std::process::Stdio::from(self.write_end.try_clone()?),
+    ))
+  }
+}
+
+struct RotatingFile {
+  path: String,
+  max_bytes: u64,
+  keep: u64,
+  size: u64,
+  file: std::fs::File,
+}
+
+fn open_append(path: &str) -> Result<std::fs::File, std::io::Error> {
+  std::fs::OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(path)
+}
+
+impl RotatingFile {
+  fn new(path: &str, max_bytes: u64, keep: u64) -> Result<Self, std::io::Error> {
+    let file = open_append(path)?;
+    let size = file.metadata()?.len();
+    Ok(RotatingFile {
+      path: path.to_string(),
+      max_bytes,
+      keep,
+      size,
+      file,
+    })
+  }
+
+  fn rotate(&mut self) -> Result<(), std::io::Error> {
+    // path.1 is the most recent rotated file, path.KEEP the oldest.
+    if self.keep == 0 {
+      std::fs::remove_file(&self.path)?;
+    } else {
+      for i in (1..self.keep).rev() {
+        let from = format!("{}.{}", self.path, i);
+        if std::path::Path::new(&from).exists() {
+          std::fs::rename(&from, format!("{}.{}", self.path, i + 1))?;
+        }
+      }
+      std::fs::rename(&self.path, format!("{}.1", self.path))?;
+    }
+    self.file = open_append(&self.path)?;
+    self.size = 0;
+    Ok(())
+  }
+
+  fn write(&mut self, buf: &[u8]) -> Result<(), std::io::Error> {
+    // Rotate on line boundaries so lines are not split across files.
+    for line in buf.split_inclusive(|b| *b == b'\n') {
+      if self.max_bytes > 0 && self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+        self.rotate()?;
+      }
+      self.file.write_all(line)?;
+      self.size += line.len() as u64;
+    }
+    Ok(())
+  }
+}
+
+// Returns the (read, write) ends of a new pipe, both are close on exec
+// so they are only inherited by children that are explicitly given them.
+pub fn pipe() -> Result<(std::fs::File, std::fs::File), std::io::Error> {
+  let mut fds = [0; 2];
+  if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+    return Err(std::io::Error::last_os_error());
+  }
+  unsafe {
+    Ok((
+      std::fs::File::from_raw_fd(fds[0]),
+      std::fs::File::from_raw_fd(fds[1]),
+    ))
+  }
+}
+
+pub fn start(path: &str, max_bytes: u64, keep: u64) -> Result<LogPipe, std::io::Error> {
+  let mut out = RotatingFile::new(path, max_bytes, keep)?;
+
+  let (mut read_end, write_end) = pipe()?;
+
+  std::thread::spawn(move || {
+    let mut buf = vec![0; 16384];
+    loop {
+      match read_end.read(&mut buf) {
+        Ok(0) => break,
+        Ok(n) => {
+          if let Err(err) = out.write(&buf[..n]) {
+            log::warn!("unable to write log file {}: {}.", out.path, err);
+          }
+        }
+        Err(ref err) if err.kind() == std::io::ErrorKind::Interrupted => (),
+        Err(err) => {
+          log::warn!("unable to read process output for {}: {}.", out.path, err);
+          break;
+        }
+      }
+    }
+  });
+
+  Ok(LogPipe { write_end })
+}
+
+// Return up to max_lines of the most recent output in a log file.
+pub fn tail(path: &str, max_lines: usize) -> Result<String, std::io::Error> {
+  let max_bytes: u64 = 65536;
+  let mut f = std::fs::File::open(path)?;
+  let size = f.metadata()?.len();
+  let start = size.saturating_sub(max_bytes);
+  f.seek(std::io::SeekFrom::Start(start))?;
+  let mut buf = vec![];
+  f.read_to_end(&mut buf)?;
+  let text = String::from_utf8_lossy(&buf);
+  let mut lines: Vec<&str> = text.lines().collect();
+  // The first line is probably partial if we didn't read from the start.
+  if start > 0 && !lines.is_empty() {
+    lines.remove(0);
+  }
+  let skip = lines.len().saturating_sub(max_lines);
+  Ok(lines[skip..].join("\n"))
+}
@@ -1,8 +1,26 @@
+mod cgroup;
+mod checks;
+mod config;
+mod control;
+mod envfile;
+mod exec;
+mod limits;
+mod listen;
+mod logs;
+mod notify;
+mod output;
+mod reaper;
 mod specs;
+mod status;
+mod users;
 use std::ops::Add;
+use std::os::unix::io::AsRawFd;
 use std::os::unix::process::CommandExt;
 use std::time::{Duration, Instant};
 
+// The first fd of the sockets passed to a run command.
+const LISTEN_FDS_START: i32 = 3;
+
 struct RateLimiter {
   capacity: f64,
   tokens: f64,
@@ -10,27 +28,124 @@ struct RateLimiter {
   last_add: std::time::Instant,
 }
 
+struct Backoff {
+  initial: Duration,
+  max: Duration,
+  multiplier: f64,
+  jitter: f64,
+  reset: Duration,
+  next: Duration,
+}
+
+// The command that failed and caused the pending restart.
+struct Failure {
+  service: Option<String>,
+  action: String,
+  reason: &'static str,
+  exit: Option<status::ExitStatus>,
+}
+
+// A command started by run_command that is being waited on.
+struct Hook {
+  // None for a kill of several processes.
+  pid: Option<u32>,
+  name: String,
+  started: Instant,
+  deadline: Option<Instant>,
+}
+
+struct ProcState {
+  phase: status::Phase,
+  starts: u64,
+  last_exit: Option<status::ExitStatus>,
+  last_check: Option<status::CheckResult>,
+  last_check_ok: Option<Instant>,
+  log: Option<logs::LogPipe>,
+  cgroup: Option<cgroup::Cgroup>,
+  oom_kills: u64,
+  notify: Option<notify::Socket>,
+  notification_fd: Option<std::fs::File>,
+  listeners: Vec<listen::Listener>,
+  ready: bool,
+  last_watchdog: Option<Instant>,
+  status_text: Option<String>,
+}
+
 struct Supervisor {
   spec: specs::SupervisorSpec,
   procs: Vec<Option<std::process::Child>>,
+  proc_states: Vec<ProcState>,
   rate_limiter: RateLimiter,
+  backoff: Backoff,
+  started_at: Option<Instant>,
+  sigtx: crossbeam_channel::Sender<Signal>,
   sigrx: crossbeam_channel::Receiver<Signal>,
+  childrx: crossbeam_channel::Receiver<()>,
+  // Signals received during a kill, handled once it completes.
+  deferred_signals: std::collections::VecDeque<Signal>,
   first_start: bool,
+  running: bool,
+  stopping: bool,
+  failed_proc: Option<usize>,
+  prefixer: Option<output::Prefixer>,
+  hook: Option<Hook>,
+  num_restarts: u128,
+  reap_orphans: bool,
+  subreaper: bool,
+  restart_reason: Option<&'static str>,
+  failure: Option<Failure>,
+  last_error: Option<&'static str>,
+  pending_spec: Option<specs::SupervisorSpec>,
+  reloading: bool,
 }
 
 enum Signal {
   Shutdown,
-  Terminate,
+  Terminate(Option<i32>),
+  Status(crossbeam_channel::Sender<String>),
+  Restart(String, crossbeam_channel::Sender<String>),
+  RestartAll(crossbeam_channel::Sender<String>),
+  Logs(String, crossbeam_channel::Sender<String>),
+  Reload(Option<crossbeam_channel::Sender<String>>),
+  Dump,
+  Notify(notify::Message),
+}
+
+// Who a command is spawned for, which decides what it inherits from
+// the proc spec.
+#[derive(Clone, Copy)]
+enum CommandKind {
+  Run(usize),
+  ProcHook(usize),
+  SupervisorHook,
 }
 
 #[derive(Debug)]
 enum SupervisorError {
-  IOError(std::io::Error),
+  IOError(#[allow(dead_code)] std::io::Error),
   Shutdown,
-  Terminated,
+  // The signal that terminated the supervisor, None if not a signal.
+  Terminated(Option<i32>),
   RestartLimitReached,
   ProcFailed,
   UnkillableChild,
+  RestartRequested,
+  ReloadRequested,
+}
+
+impl SupervisorError {
+  fn name(&self) -> &'static str {
+    match self {
+      SupervisorError::IOError(_) => "IOError",
+      SupervisorError::Shutdown => "Shutdown",
+      SupervisorError::Terminated(_) => "Terminated",
+      SupervisorError::RestartLimitReached => "RestartLimitReached",
+      SupervisorError::ProcFailed => "ProcFailed",
+      SupervisorError::UnkillableChild => "UnkillableChild",
+      SupervisorError::RestartRequested => "RestartRequested",
+      SupervisorError::ReloadRequested => "ReloadRequested",
+    }
+  }
 }
 
 impl From<std::io::Error> for SupervisorError {
@@ -66,6 +181,11 @@ impl RateLimiter {
     }
   }
 
+  pub fn available(&self) -> f64 {
+    let elapsed = self.last_add.elapsed().as_secs_f64();
+    (self.tokens + elapsed * self.tokens_per_sec).min(self.capacity)
+  }
+
   fn add_tokens(&mut self) {
     let now = std::time::Instant::now();
     let duration = now.duration_since(self.last_add);
@@ -79,25 +199,198 @@ impl RateLimiter {
   }
 }
 
+impl Backoff {
+  pub fn new(spec: &specs::SupervisorSpec) -> Self {
+    let seconds = |secs: f64| Duration::from_millis((secs.max(0.0) * 1000.0) as u64);
+    Backoff {
+      initial: seconds(spec.restart_backoff_seconds),
+      max: seconds(spec.restart_backoff_max_seconds),
+      multiplier: spec.restart_backoff_multiplier.max(1.0),
+      jitter: spec.restart_backoff_jitter.clamp(0.0, 1.0),
+      reset: seconds(spec.restart_backoff_reset_seconds),
+      next: seconds(spec.restart_backoff_seconds),
+    }
+  }
+
+  // The delay before the next restart, processes that ran for the
+  // reset period before failing start over from the initial delay.
+  pub fn next_delay(&mut self, ran_for: Duration) -> Duration {
+    if ran_for >= self.reset {
+      self.next = self.initial;
+    }
+    let delay = self.next;
+    // Computed in f64 and capped before converting back, so a large
+    // multiplier can't overflow the duration.
+    let next = (self.next.as_secs_f64() * self.multiplier).min(self.max.as_secs_f64());
+    self.next = Duration::try_from_secs_f64(next).unwrap_or(self.max);
+    // Jitter stops supervisors that failed together from restarting in lockstep.
+    let jitter = 1.0 + self.jitter * (2.0 * random_fraction() - 1.0);
+    Duration::try_from_secs_f64(delay.as_secs_f64() * jitter).unwrap_or(delay)
+  }
+}
+
+fn random_fraction() -> f64 {
+  use std::hash::{BuildHasher, Hasher};
+  let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+  hasher.write_u128(
+    std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_nanos(),
+  );
+  (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
 impl Supervisor {
-  fn new(spec: specs::SupervisorSpec, sigrx: crossbeam_channel::Receiver<Signal>) -> Self {
+  fn new(
+    spec: specs::SupervisorSpec,
+    sigtx: crossbeam_channel::Sender<Signal>,
+    sigrx: crossbeam_channel::Receiver<Signal>,
+    childrx: crossbeam_channel::Receiver<()>,
+  ) -> Result<Self, std::io::Error> {
     let mut procs = vec![];
-    for _i in spec.procs.iter() {
+    let mut proc_states = vec![];
+    for s in spec.procs.iter() {
       procs.push(None);
+      proc_states.push(Supervisor::new_proc_state(&spec, s, &sigtx)?);
     }
 
     let rate_limiter = RateLimiter::new(spec.max_restart_tokens, spec.restart_tokens_per_second);
+    let backoff = Backoff::new(&spec);
+    let prefixer = output::Prefixer::new(&spec);
+    let subreaper = match reaper::set_child_subreaper() {
+      Ok(subreaper) => subreaper,
+      Err(err) => {
+        log::warn!("unable to become a child subreaper: {}.", err);
+        false
+      }
+    };
 
-    Supervisor {
+    Ok(Supervisor {
       spec,
       procs,
+      proc_states,
+      sigtx,
       sigrx,
+      childrx,
+      deferred_signals: std::collections::VecDeque::new(),
       rate_limiter,
+      backoff,
+      started_at: None,
       first_start: true,
+      running: false,
+      stopping: false,
+      failed_proc: None,
+      prefixer,
+      hook: None,
+      num_restarts: 0,
+      // As pid 1 or a subreaper, orphans become our children.
+      reap_orphans: subreaper || std::process::id() == 1,
+      subreaper,
+      restart_reason: None,
+      failure: None,
+      last_error: None,
+      pending_spec: None,
+      reloading: false,
+    })
+  }
+
+  fn new_proc_state(
+    spec: &specs::SupervisorSpec,
+    s: &specs::ProcSpec,
+    sigtx: &crossbeam_channel::Sender<Signal>,
+  ) -> Result<ProcState, std::io::Error> {
+    let cgroup = Supervisor::create_cgroup(spec, s);
+    let oom_kills = cgroup.as_ref().map(|c| c.oom_kills()).unwrap_or(0);
+    let notify = if s.notify || s.watchdog_seconds.is_some() {
+      let cgroup_path = cgroup.as_ref().map(|c| c.path().to_path_buf());
+      // Without a socket the proc can never become ready.
+      let socket = notify::Socket::bind(&s.name, cgroup_path, sigtx.clone()).map_err(|err| {
+        std::io::Error::new(
+          err.kind(),
+          format!("unable to create notify socket for {}: {}", s.name, err),
+        )
+      })?;
+      Some(socket)
+    } else {
+      None
+    };
+    Ok(ProcState {
+      phase: status::Phase::Stopped,
+      starts: 0,
+      last_exit: None,
+      last_check: None,
+      last_check_ok: None,
+      log: None,
+      cgroup,
+      oom_kills,
+      notify,
+      notification_fd: None,
+      listeners: vec![],
+      ready: false,
+      last_watchdog: None,
+      status_text: None,
+    })
+  }
+
+  fn create_cgroup(spec: &specs::SupervisorSpec, s: &specs::ProcSpec) -> Option<cgroup::Cgroup> {
+    let has_limits = s.memory_max.is_some() || s.cpu_max.is_some() || s.pids_max.is_some();
+    let parent = match spec.cgroup_parent {
+      Some(ref parent) => parent,
+      None => {
+        if has_limits {
+          log::warn!(
+            "{} has resource limits but no -cgroup-parent, ignoring them.",
+            s.name
+          );
+        }
+        return None;
+      }
+    };
+
+    let cgroup = match cgroup::Cgroup::create(parent, &s.name) {
+      Ok(cgroup) => cgroup,
+      Err(err) => {
+        log::warn!(
+          "unable to create cgroup for {} in {}, falling back to process groups: {}.",
+          s.name,
+          parent,
+          err
+        );
+        if has_limits {
+          log::warn!("resource limits for {} are ignored.", s.name);
+        }
+        return None;
+      }
+    };
+
+    let limits = [
+      ("memory-max", s.memory_max.map(|v| cgroup.set_memory_max(v))),
+      ("cpu-max", s.cpu_max.map(|v| cgroup.set_cpu_max(v))),
+      ("pids-max", s.pids_max.map(|v| cgroup.set_pids_max(v))),
+    ];
+    for (limit, result) in limits.iter() {
+      if let Some(Err(err)) = result {
+        log::warn!("unable to set {} for {}: {}.", limit, s.name, err);
+      }
     }
+
+    Some(cgroup)
   }
 
-  fn write_status_file(&mut self, status: &str) -> Result<(), SupervisorError> {
+  fn write_status_file(&mut self) -> Result<(), SupervisorError> {
+    let status = match self.spec.status_format {
+      specs::StatusFormat::Text => String::from(if self.first_start {
+        "STARTING\n"
+      } else {
+        "RUNNING\n"
+      }),
+      specs::StatusFormat::Json => match serde_json::to_string_pretty(&self.status()) {
+        Ok(status) => status + "\n",
+        Err(e) => return Err(SupervisorError::IOError(e.into())),
+      },
+    };
+
     match self.spec.status_file {
       Some(ref status_file) => {
         let status_file = std::path::PathBuf::from(status_file);
@@ -118,111 +411,603 @@ impl Supervisor {
     }
   }
 
-  fn check_signals(&mut self) -> Result<(), SupervisorError> {
-    match self.sigrx.try_recv() {
-      Ok(Signal::Shutdown) => return Err(SupervisorError::Shutdown),
-      Ok(Signal::Terminate) => return Err(SupervisorError::Terminated),
-      _ => Ok(()),
+  fn set_phase(&mut self, idx: usize, phase: status::Phase) {
+    self.proc_states[idx].phase = phase;
+    // The text status file only changes on startup, so only json is updated.
+    if self.spec.status_format == specs::StatusFormat::Json {
+      if let Err(e) = self.write_status_file() {
+        log::warn!("unable to update status file: {:?}.", e);
+      }
     }
   }
 
-  fn sleep(&mut self, d: Duration) -> Result<(), SupervisorError> {
-    crossbeam_channel::select! {
-      recv(self.sigrx) -> sig => if let Ok(sig) = sig {
-        match sig {
-          Signal::Shutdown => return Err(SupervisorError::Shutdown),
-          Signal::Terminate => return Err(SupervisorError::Terminated),
+  fn record_exit(&mut self, idx: usize, rc: std::process::ExitStatus) {
+    log::info!("{} exited with {}.", self.spec.procs[idx].name, rc);
+    self.proc_states[idx].last_exit = Some(rc.into());
+
+    if let Some(ref cgroup) = self.proc_states[idx].cgroup {
+      let oom_kills = cgroup.oom_kills();
+      if oom_kills > self.proc_states[idx].oom_kills {
+        log::warn!(
+          "{} was killed by the OOM killer.",
+          self.spec.procs[idx].name
+        );
+        self.proc_states[idx].oom_kills = oom_kills;
+        self.restart_reason = Some("OOM");
+      }
+    }
+  }
+
+  fn set_failure(
+    &mut self,
+    idx: Option<usize>,
+    action: &str,
+    reason: &'static str,
+    exit: Option<std::process::ExitStatus>,
+  ) {
+    self.failure = Some(Failure {
+      service: idx.map(|idx| self.spec.procs[idx].name.clone()),
+      action: action.to_string(),
+      reason,
+      exit: exit.map(|rc| rc.into()),
+    });
+  }
+
+  fn state(&self) -> &'static str {
+    if self.stopping {
+      "stopping"
+    } else if self.first_start {
+      "starting"
+    } else if !self.running {
+      "restarting"
+    } else {
+      "running"
+    }
+  }
+
+  fn status(&mut self) -> status::SupervisorStatus {
+    status::SupervisorStatus {
+      state: self.state(),
+      time: status::now(),
+      restart_tokens: self.rate_limiter.available(),
+      procs: self
+        .spec
+        .procs
+        .iter()
+        .zip(self.procs.iter())
+        .zip(self.proc_states.iter())
+        .map(|((s, p), st)| status::ProcStatus {
+          name: s.name.clone(),
+          phase: st.phase,
+          pid: p.as_ref().map(|c| c.id()),
+          restarts: st.starts.saturating_sub(1),
+          last_exit: st.last_exit,
+          last_check: st.last_check,
+          status_text: st.status_text.clone(),
+        })
+        .collect(),
+    }
+  }
+
+  fn handle_signal(&mut self, sig: Signal) -> Result<(), SupervisorError> {
+    match sig {
+      Signal::Shutdown => Err(SupervisorError::Shutdown),
+      Signal::Terminate(signo) => Err(SupervisorError::Terminated(signo)),
+      Signal::Status(reply) => {
+        let status =
+          serde_json::to_string_pretty(&self.status()).unwrap_or_else(|e| format!("error: {}", e));
+        let _ = reply.send(status);
+        Ok(())
+      }
+      Signal::Restart(name, reply) => match self.spec.procs.iter().position(|p| p.name == name) {
+        Some(idx) => {
+          log::info!("restart of {} requested.", name);
+          let _ = reply.send(String::from("ok"));
+          self.failed_proc = Some(idx);
+          Err(SupervisorError::RestartRequested)
+        }
+        None => {
+          let _ = reply.send(format!("error: no proc named '{}'", name));
+          Ok(())
         }
-      } else {
-        return Err(SupervisorError::Terminated)
       },
-      default(d) => (),
+      Signal::RestartAll(reply) => {
+        log::info!("restart of all procs requested.");
+        let _ = reply.send(String::from("ok"));
+        self.failed_proc = None;
+        Err(SupervisorError::RestartRequested)
+      }
+      Signal::Logs(name, reply) => {
+        let response = match self.spec.procs.iter().find(|p| p.name == name) {
+          Some(s) => match s.log_file {
+            Some(ref path) => match logs::tail(path, 100) {
+              Ok(lines) => lines,
+              Err(e) => format!("error: unable to read {}: {}", path, e),
+            },
+            None => format!("error: no log file configured for '{}'", name),
+          },
+          None => format!("error: no proc named '{}'", name),
+        };
+        let _ = reply.send(response);
+        Ok(())
+      }
+      Signal::Dump => {
+        self.dump_state();
+        Ok(())
+      }
+      Signal::Notify(msg) => {
+        if let Some(idx) = self.spec.procs.iter().position(|p| p.name == msg.service) {
+          // Messages received before the current start are stale.
+          if msg.generation != self.proc_states[idx].starts {
+            return Ok(());
+          }
+          for (key, value) in msg.vars {
+            match key.as_str() {
+              "READY" if value == "1" => self.proc_states[idx].ready = true,
+              "WATCHDOG" if value == "1" => {
+                self.proc_states[idx].last_watchdog = Some(Instant::now())
+              }
+              "STATUS" => self.proc_states[idx].status_text = Some(value),
+              _ => (),
+            }
+          }
+        }
+        Ok(())
+      }
+      Signal::Reload(reply) => {
+        let args: Vec<String> = std::env::args().collect();
+        match parse_spec(&args) {
+          Ok(spec) => {
+            log::info!("reload requested.");
+            if let Some(reply) = reply {
+              let _ = reply.send(String::from("ok"));
+            }
+            self.pending_spec = Some(spec);
+            Err(SupervisorError::ReloadRequested)
+          }
+          Err(err) => {
+            log::error!("unable to reload: {}", err);
+            if let Some(reply) = reply {
+              let _ = reply.send(format!("error: {}", err.trim_end_matches('.')));
+            }
+            Ok(())
+          }
+        }
+      }
+    }
+  }
+
+  // Logged on SIGUSR1, to see what a stuck supervisor is waiting on.
+  fn dump_state(&mut self) {
+    log::info!(
+      "state: {}, {:.2} restart tokens, {} restarts.",
+      self.state(),
+      self.rate_limiter.available(),
+      self.num_restarts
+    );
+    for ((s, p), st) in self
+      .spec
+      .procs
+      .iter()
+      .zip(self.procs.iter())
+      .zip(self.proc_states.iter())
+    {
+      let pid = match p {
+        Some(c) => c.id().to_string(),
+        None => String::from("none"),
+      };
+      let check = match st.last_check_ok {
+        Some(t) => format!("{:.1}s ago", t.elapsed().as_secs_f64()),
+        None => String::from("never"),
+      };
+      log::info!(
+        "proc {}: pid {}, {}, last successful check {}.",
+        s.name,
+        pid,
+        st.phase.name(),
+        check
+      );
+    }
+    match self.hook {
+      Some(ref hook) => {
+        let deadline = match hook.deadline {
+          Some(deadline) => format!(
+            "{:.1}s",
+            deadline
+              .saturating_duration_since(hook.started)
+              .as_secs_f64()
+          ),
+          None => String::from("no"),
+        };
+        let pid = match hook.pid {
+          Some(pid) => format!(" (pid {})", pid),
+          None => String::new(),
+        };
+        log::info!(
+          "running {}{} for {:.1}s of {} timeout.",
+          hook.name,
+          pid,
+          hook.started.elapsed().as_secs_f64(),
+          deadline
+        );
+      }
+      None => log::info!("no command running."),
+    }
+  }
+
+  fn check_signals(&mut self) -> Result<(), SupervisorError> {
+    while let Some(sig) = self.deferred_signals.pop_front() {
+      self.handle_signal(sig)?;
+    }
+    while let Ok(sig) = self.sigrx.try_recv() {
+      self.handle_signal(sig)?;
     }
     Ok(())
   }
 
-  fn kill_child_tree(
+  // Zombies we did not spawn ourselves are reparented orphans that
+  // nobody else will wait on. Peeking with WNOWAIT leaves our own children
+  // for their owners to wait on, though a zombie of our own that has not
+  // been waited on yet hides any orphans behind it until the next call.
+  fn reap_orphans(&mut self) {
+    if !self.reap_orphans {
+      return;
+    }
+    loop {
+      let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+      let rc = unsafe {
+        libc::waitid(
+          libc::P_ALL,
+          0,
+          &mut info,
+          libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+      };
+      let pid = unsafe { info.si_pid() };
+      if rc != 0 || pid == 0 {
+        return;
+      }
+      if self.is_owned(pid) {
+        return;
+      }
+      unsafe { libc::waitpid(pid, std::ptr::null_mut(), libc::WNOHANG) };
+    }
+  }
+
+  fn is_owned(&self, pid: i32) -> bool {
+    self.hook.as_ref().and_then(|h| h.pid) == Some(pid as u32)
+      || self
+        .procs
+        .iter()
+        .any(|p| p.as_ref().map(|c| c.id()) == Some(pid as u32))
+  }
+
+  // Wait until a child exits, a signal interrupts us, or the deadline
+  // passes. Child exits are coalesced and may be for a child the caller
+  // is not interested in, so callers must always check the state they are
+  // waiting on before calling this again.
+  fn wait_event(&mut self, deadline: Option<Instant>) -> Result<(), SupervisorError> {
+    self.reap_orphans();
+
+    if let Some(sig) = self.deferred_signals.pop_front() {
+      return self.handle_signal(sig);
+    }
+
+    let timeout = match deadline {
+      Some(deadline) => {
+        let now = Instant::now();
+        if now >= deadline {
+          return Ok(());
+        }
+        deadline - now
+      }
+      // Spurious wakeups are harmless, so just wait a long time.
+      None => Duration::from_secs(3600),
+    };
+    crossbeam_channel::select! {
+      // A handled signal may have changed what the caller waits on.
+      recv(self.sigrx) -> sig => match sig {
+        Ok(sig) => self.handle_signal(sig),
+        Err(_) => Err(SupervisorError::Terminated(None)),
+      },
+      recv(self.childrx) -> _ => Ok(()),
+      default(timeout) => Ok(()),
+    }
+  }
+
+  // Kills are not interrupted by signals, they are handled once the kill
+  // completes. A dump is the exception, as a kill that hangs is exactly
+  // what it is for.
+  fn wait_kill_event(&mut self, timeout: Duration) {
+    crossbeam_channel::select! {
+      recv(self.sigrx) -> sig => match sig {
+        Ok(Signal::Dump) => self.dump_state(),
+        Ok(sig) => self.deferred_signals.push_back(sig),
+        Err(_) => (),
+      },
+      recv(self.childrx) -> _ => (),
+      default(timeout) => (),
+    }
+  }
+
+  // Escalating to SIGKILL gives a kill a new deadline.
+  fn set_kill_deadline(&mut self, deadline: Option<Instant>) {
+    if let Some(ref mut hook) = self.hook {
+      hook.deadline = deadline;
+    }
+  }
+
+  fn wait_child_exit(
+    &mut self,
+    try_wait: &mut impl FnMut(&mut Self) -> std::io::Result<Option<std::process::ExitStatus>>,
+    deadline: Option<Instant>,
+  ) -> bool {
+    loop {
+      match try_wait(self) {
+        Err(_) => return false,
+        Ok(None) => (),
+        Ok(Some(_)) => return true,
+      }
+      let timeout = match deadline {
+        Some(deadline) => {
+          let now = Instant::now();
+          if now >= deadline {
+            return false;
+          }
+          deadline - now
+        }
+        None => Duration::from_secs(3600),
+      };
+      self.wait_kill_event(timeout);
+    }
+  }
+
+  // A kill is shown as the running command in a dump, then whatever was
+  // running before it is restored.
+  fn track_kill<T>(
+    &mut self,
+    name: String,
+    pid: Option<u32>,
+    deadline: Option<Instant>,
+    kill: impl FnOnce(&mut Self) -> T,
+  ) -> T {
+    let hook = self.hook.replace(Hook {
+      pid,
+      name,
+      started: Instant::now(),
+      deadline,
+    });
+    let result = kill(self);
+    self.hook = hook;
+    result
+  }
+
+  // Kills a command that is not one of the procs.
+  fn kill_command(
+    &mut self,
     c: &mut std::process::Child,
+    name: String,
     deadline: Option<Instant>,
   ) -> Result<(), SupervisorError> {
-    // We busy wait here as it is simpler, if we are killing the process
-    // the supervisor has work to do anyway, so it doesn't waste that much cpu.
+    self.kill_child_tree(c.id(), name, deadline, |_| c.try_wait())
+  }
+
+  // The child is polled with try_wait rather than borrowed, as a proc's
+  // child stays in procs while it is killed.
+  fn kill_child_tree(
+    &mut self,
+    pid: u32,
+    name: String,
+    deadline: Option<Instant>,
+    mut try_wait: impl FnMut(&mut Self) -> std::io::Result<Option<std::process::ExitStatus>>,
+  ) -> Result<(), SupervisorError> {
+    self.track_kill(name, Some(pid), deadline, |s| {
+      s.kill_child_tree_tracked(pid, deadline, &mut try_wait)
+    })
+  }
 
+  fn kill_child_tree_tracked(
+    &mut self,
+    pid: u32,
+    deadline: Option<Instant>,
+    try_wait: &mut impl FnMut(&mut Self) -> std::io::Result<Option<std::process::ExitStatus>>,
+  ) -> Result<(), SupervisorError> {
     // First try a SIGTERM, let the process do whatever cleanup it needs to do.
 
-    let rc = unsafe { libc::kill(-(c.id() as i32), libc::SIGTERM) };
+    let rc = unsafe { libc::kill(-(pid as i32), libc::SIGTERM) };
     if rc != 0 {
       log::warn!("sending SIGTERM to process group failed.");
     }
 
-    loop {
-      if let Some(deadline) = deadline {
-        if Instant::now() >= deadline {
-          break;
-        }
-      }
-      match c.try_wait() {
-        Err(_) => break, /* Go straight to kill */
-        Ok(None) => (),
-        Ok(Some(_)) => return Ok(()),
-      }
-      std::thread::sleep(Duration::from_millis(10));
+    if self.wait_child_exit(try_wait, deadline) {
+      return Ok(());
     }
 
     log::warn!("child did not respond to SIGTERM, trying SIGKILL.");
 
-    let rc = unsafe { libc::kill(-(c.id() as i32), libc::SIGKILL) };
+    let rc = unsafe { libc::kill(-(pid as i32), libc::SIGKILL) };
     if rc != 0 {
       log::warn!("killing process group failed.");
     }
 
-    for _ in 0..1000 {
-      match c.try_wait() {
-        Err(_) => (),
-        Ok(None) => (),
-        Ok(_) => return Ok(()),
-      }
-      std::thread::sleep(Duration::from_millis(10));
+    let kill_deadline = Instant::now().add(Duration::from_secs(10));
+    self.set_kill_deadline(Some(kill_deadline));
+    if self.wait_child_exit(try_wait, Some(kill_deadline)) {
+      return Ok(());
     }
 
     Err(SupervisorError::UnkillableChild)
   }
 
+  // The run command of a proc, unlike the other commands, gets the
+  // proc's log file, cgroup and limits.
   fn spawn_child(
-    command: &str,
+    &self,
+    command: &[String],
     env: &Vec<(String, String)>,
+    kind: CommandKind,
+    pid_vars: &[&str],
   ) -> Result<std::process::Child, SupervisorError> {
-    let mut cmd = std::process::Command::new(command);
+    let mut cmd = std::process::Command::new(&command[0]);
+    cmd.args(&command[1..]);
     cmd.stdin(std::process::Stdio::null());
+
+    let run_idx = match kind {
+      CommandKind::Run(idx) => Some(idx),
+      _ => None,
+    };
+    let state = run_idx.map(|idx| &self.proc_states[idx]);
+    let log = state.and_then(|s| s.log.as_ref());
+    let cgroup_fd = state.and_then(|s| s.cgroup.as_ref()).map(|c| c.procs_fd());
+    let notification_fd = run_idx.and_then(|idx| {
+      let target = self.spec.procs[idx].notification_fd?;
+      let fd = self.proc_states[idx].notification_fd.as_ref()?.as_raw_fd();
+      Some((fd, target))
+    });
+    let listen_fds: Vec<i32> = state
+      .map(|s| s.listeners.iter().map(|l| l.fd()).collect())
+      .unwrap_or_default();
+    let mut listen_tmp_fds = vec![0; listen_fds.len()];
+    // Temporary fds must not land on any fd we are about to replace.
+    let listen_tmp_min = notification_fd
+      .map(|(_, target)| target + 1)
+      .unwrap_or(0)
+      .max(LISTEN_FDS_START + listen_fds.len() as i32);
+    let limits = run_idx.map(|idx| limits::Limits::prepare(&self.spec.procs[idx]));
+    let credentials = match kind {
+      CommandKind::Run(idx) => self.spec.procs[idx].run_credentials.clone(),
+      CommandKind::ProcHook(idx) => self.spec.procs[idx].hook_credentials.clone(),
+      CommandKind::SupervisorHook => None,
+    };
+
+    let output = match (log, &self.prefixer) {
+      (Some(log), _) => Some(log.stdio()?),
+      (None, Some(prefixer)) => {
+        // The script env already says who is running and why.
+        let name = lookup_env(env, "ORDERLY_SERVICE_NAME").unwrap_or("orderly");
+        let action = lookup_env(env, "ORDERLY_ACTION").unwrap_or("");
+        Some(prefixer.stdio(name, action)?)
+      }
+      (None, None) => None,
+    };
+    if let Some((stdout, stderr)) = output {
+      cmd.stdout(stdout);
+      cmd.stderr(stderr);
+    }
+    let mut environments = vec![&self.spec.environment];
+    if let CommandKind::Run(idx) | CommandKind::ProcHook(idx) = kind {
+      environments.push(&self.spec.procs[idx].environment);
+    }
+    let clear_env = environments.iter().any(|e| e.clear_env);
+    if clear_env {
+      cmd.env_clear();
+      for name in environments.iter().flat_map(|e| e.pass_env.iter()) {
+        if let Some(value) = std::env::var_os(name) {
+          cmd.env(name, value);
+        }
+      }
+    }
+    for e in environments.iter() {
+      if let Some(ref dir) = e.dir {
+        cmd.current_dir(dir);
+      }
+      for v in e.env.iter() {
+        cmd.env(&v.0, &v.1);
+      }
+    }
     for v in env {
       cmd.env(&v.0, &v.1);
     }
-    cmd.before_exec(|| {
-      match nix::unistd::setpgid(nix::unistd::Pid::from_raw(0), nix::unistd::Pid::from_raw(0)) {
-        Ok(_pid) => Ok(()),
-        Err(_err) => Err(std::io::Error::from(std::io::ErrorKind::Other)),
+    // Pid variables need the final environment, so the command is
+    // exec'd by hand rather than by Command.
+    let mut exec = if pid_vars.is_empty() {
+      None
+    } else {
+      let mut vars: std::collections::BTreeMap<_, _> = if clear_env {
+        Default::default()
+      } else {
+        std::env::vars_os().collect()
+      };
+      for (k, v) in cmd.get_envs() {
+        match v {
+          Some(v) => vars.insert(k.to_owned(), v.to_owned()),
+          None => vars.remove(k),
+        };
       }
-    });
+      Some(exec::prepare(
+        command,
+        vars.into_iter().collect(),
+        pid_vars,
+      )?)
+    };
+    unsafe {
+      cmd.pre_exec(move || {
+        if nix::unistd::setpgid(nix::unistd::Pid::from_raw(0), nix::unistd::Pid::from_raw(0))
+          .is_err()
+        {
+          return Err(std::io::Error::from(std::io::ErrorKind::Other));
+        }
+        if let Some(fd) = cgroup_fd {
+          if libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1) != 1 {
+            return Err(std::io::Error::last_os_error());
+          }
+        }
+        if let Some(ref limits) = limits {
+          limits.apply()?;
+        }
+        // Listeners are moved out of the way first, so placing one fd
+        // can not close another that is still to be placed.
+        for (i, fd) in listen_fds.iter().enumerate() {
+          listen_tmp_fds[i] = libc::fcntl(*fd, libc::F_DUPFD_CLOEXEC, listen_tmp_min);
+          if listen_tmp_fds[i] < 0 {
+            return Err(std::io::Error::last_os_error());
+          }
+        }
+        // dup2 clears close on exec, unless the pipe is already on the
+        // right fd.
+        if let Some((fd, target)) = notification_fd {
+          if fd == target {
+            if libc::fcntl(fd, libc::F_SETFD, 0) != 0 {
+              return Err(std::io::Error::last_os_error());
+            }
+          } else if libc::dup2(fd, target) < 0 {
+            return Err(std::io::Error::last_os_error());
+          }
+        }
+        for (i, fd) in listen_tmp_fds.iter().enumerate() {
+          if libc::dup2(*fd, LISTEN_FDS_START + i as i32) < 0 {
+            return Err(std::io::Error::last_os_error());
+          }
+        }
+        // Last, as dropping privileges may stop the above from working.
+        if let Some(ref credentials) = credentials {
+          credentials.apply()?;
+        }
+        if let Some(ref mut exec) = exec {
+          return Err(exec.exec());
+        }
+        Ok(())
+      });
+    }
     Ok(cmd.spawn()?)
   }
 
   fn deadline_from_float_seconds(start: Instant, timeout_seconds: Option<f64>) -> Option<Instant> {
-    match timeout_seconds {
-      Some(secs) => Some(start.add(Duration::from_millis((secs * 1000.0) as u64))),
-      None => None,
-    }
+    timeout_seconds.map(|secs| start.add(Duration::from_millis((secs * 1000.0) as u64)))
   }
 
   fn run_command_timeout_secs(
     &mut self,
-    command: &str,
+    command: &[String],
     env: &Vec<(String, String)>,
+    kind: CommandKind,
     timeout_secs: Option<f64>,
     depends_on_proc: Option<usize>,
   ) -> Result<(), SupervisorError> {
     self.run_command(
       command,
       env,
+      kind,
       Supervisor::deadline_from_float_seconds(Instant::now(), timeout_secs),
       depends_on_proc,
     )
@@ -230,39 +1015,76 @@ impl Supervisor {
 
   fn run_command(
     &mut self,
-    command: &str,
+    command: &[String],
     env: &Vec<(String, String)>,
+    kind: CommandKind,
     deadline: Option<Instant>,
     depends_on_proc: Option<usize>,
   ) -> Result<(), SupervisorError> {
-    let mut c = Supervisor::spawn_child(command, env)?;
+    let mut c = self.spawn_child(command, env, kind, &[])?;
+    let action = lookup_env(env, "ORDERLY_ACTION").unwrap_or("");
+    self.hook = Some(Hook {
+      pid: Some(c.id()),
+      name: self.command_name(kind, action),
+      started: Instant::now(),
+      deadline,
+    });
+    let result = self.wait_command(&mut c, kind, action, deadline, depends_on_proc);
+    self.hook = None;
+    result
+  }
 
-    let max_delay: u64 = 500;
-    let mut delay: u64 = 10;
+  fn command_name(&self, kind: CommandKind, action: &str) -> String {
+    match kind {
+      CommandKind::Run(idx) | CommandKind::ProcHook(idx) => {
+        format!("{} {}", self.spec.procs[idx].name, action)
+      }
+      CommandKind::SupervisorHook => String::from(action),
+    }
+  }
 
+  fn wait_command(
+    &mut self,
+    c: &mut std::process::Child,
+    kind: CommandKind,
+    action: &str,
+    deadline: Option<Instant>,
+    depends_on_proc: Option<usize>,
+  ) -> Result<(), SupervisorError> {
+    let proc_idx = match kind {
+      CommandKind::Run(idx) | CommandKind::ProcHook(idx) => Some(idx),
+      CommandKind::SupervisorHook => None,
+    };
+    let kill_name = format!("{} KILL", self.command_name(kind, action));
     loop {
-      self.check_signals()?;
+      if let Err(e) = self.check_signals() {
+        return Err(self.abandon_command(c, kill_name, e));
+      }
 
       if let Some(deadline) = deadline {
         let now = Instant::now();
         if now > deadline {
-          Supervisor::kill_child_tree(&mut c, Some(now.add(Duration::from_secs(10))))?;
+          self.kill_command(c, kill_name, Some(now.add(Duration::from_secs(10))))?;
+          self.set_failure(proc_idx, action, "timeout", None);
           return Err(SupervisorError::ProcFailed);
         }
       }
 
       if let Some(idx) = depends_on_proc {
-        let ok = match self.procs[idx] {
+        let (ok, exit) = match self.procs[idx] {
           Some(ref mut p) => match p.try_wait() {
-            Ok(None) => true,
-            _ => false,
+            Ok(None) => (true, None),
+            Ok(Some(rc)) => (false, Some(rc)),
+            Err(_) => (false, None),
           },
-          None => false,
+          None => (false, None),
         };
 
         if !ok {
-          Supervisor::kill_child_tree(
-            &mut c,
+          self.set_failure(Some(idx), "RUN", "exited", exit);
+          self.kill_command(
+            c,
+            kill_name,
             Supervisor::deadline_from_float_seconds(Instant::now(), Some(10.0)),
           )?;
           return Err(SupervisorError::ProcFailed);
@@ -274,26 +1096,88 @@ impl Supervisor {
           return if rc.success() {
             Ok(())
           } else {
+            self.set_failure(proc_idx, action, "exited", Some(rc));
             Err(SupervisorError::ProcFailed)
           };
         }
         None => {
-          self.sleep(Duration::from_millis(delay))?;
-          delay += 50;
-          if delay > max_delay {
-            delay = max_delay
+          if let Err(e) = self.wait_event(deadline) {
+            return Err(self.abandon_command(c, kill_name, e));
           }
         }
       };
     }
   }
 
-  fn get_supervisor_script_env(action: &str) -> Vec<(String, String)> {
-    vec![(String::from("ORDERLY_ACTION"), String::from(action))]
+  // A command interrupted by a signal or control request should not be
+  // left running unsupervised.
+  fn abandon_command(
+    &mut self,
+    c: &mut std::process::Child,
+    name: String,
+    e: SupervisorError,
+  ) -> SupervisorError {
+    match self.kill_command(
+      c,
+      name,
+      Supervisor::deadline_from_float_seconds(Instant::now(), Some(10.0)),
+    ) {
+      Ok(()) => e,
+      Err(kill_err) => kill_err,
+    }
+  }
+
+  fn get_supervisor_script_env(&self, action: &str) -> Vec<(String, String)> {
+    let mut env = vec![(String::from("ORDERLY_ACTION"), String::from(action))];
+
+    if let Some(reason) = self.restart_reason {
+      env.push((String::from("ORDERLY_RESTART_REASON"), String::from(reason)));
+    }
+
+    env
+  }
+
+  // The restart and failure commands are also told what went wrong.
+  fn get_failure_script_env(&mut self, action: &str) -> Vec<(String, String)> {
+    let mut env = self.get_supervisor_script_env(action);
+
+    env.push((
+      String::from("ORDERLY_RESTART_COUNT"),
+      self.num_restarts.to_string(),
+    ));
+    env.push((
+      String::from("ORDERLY_RESTART_TOKENS"),
+      format!("{:.2}", self.rate_limiter.available()),
+    ));
+    if let Some(error) = self.last_error {
+      env.push((String::from("ORDERLY_ERROR"), String::from(error)));
+    }
+
+    if let Some(ref failure) = self.failure {
+      if let Some(ref service) = failure.service {
+        env.push((String::from("ORDERLY_FAILED_SERVICE"), service.clone()));
+      }
+      env.push((
+        String::from("ORDERLY_FAILED_ACTION"),
+        failure.action.clone(),
+      ));
+      env.push((
+        String::from("ORDERLY_FAILURE_REASON"),
+        String::from(failure.reason),
+      ));
+      if let Some(code) = failure.exit.and_then(|e| e.code) {
+        env.push((String::from("ORDERLY_EXIT_CODE"), code.to_string()));
+      }
+      if let Some(signal) = failure.exit.and_then(|e| e.signal) {
+        env.push((String::from("ORDERLY_EXIT_SIGNAL"), signal.to_string()));
+      }
+    }
+
+    env
   }
 
   fn get_proc_script_env(&mut self, action: &str, idx: usize) -> Vec<(String, String)> {
-    let mut env = Supervisor::get_supervisor_script_env(action);
+    let mut env = self.get_supervisor_script_env(action);
 
     env.push((
       String::from("ORDERLY_SERVICE_NAME"),
@@ -310,22 +1194,31 @@ impl Supervisor {
   fn kill_proc(&mut self, idx: usize) -> Result<(), SupervisorError> {
     // Kill is not affected by signals...
 
-    let p = &mut self.procs[idx];
+    if self.procs[idx].is_some() {
+      self.set_phase(idx, status::Phase::Killing);
+    }
 
-    match p {
-      Some(c) => {
-        log::info!("killing {}.", self.spec.procs[idx].name.as_str());
-
-        Supervisor::kill_child_tree(
-          c,
-          Supervisor::deadline_from_float_seconds(
-            Instant::now(),
-            self.spec.procs[idx].terminate_timeout_seconds,
-          ),
-        )?;
-        *p = None;
+    if let Some(pid) = self.procs[idx].as_ref().map(|c| c.id()) {
+      let name = self.spec.procs[idx].name.clone();
+      log::info!("killing {}.", name);
+
+      self.kill_child_tree(
+        pid,
+        format!("{} KILL", name),
+        Supervisor::deadline_from_float_seconds(
+          Instant::now(),
+          self.spec.procs[idx].terminate_timeout_seconds,
+        ),
+        |s| s.procs[idx].as_mut().map_or(Ok(None), |c| c.try_wait()),
+      )?;
+      let rc = match self.procs[idx] {
+        Some(ref mut c) => c.try_wait()?,
+        None => None,
+      };
+      self.procs[idx] = None;
+      if let Some(rc) = rc {
+        self.record_exit(idx, rc);
       }
-      None => (),
     };
 
     self.clean_proc(idx)?;
@@ -337,6 +1230,7 @@ impl Supervisor {
     self.check_signals()?;
 
     log::info!("shutting down {}.", self.spec.procs[idx].name.as_str());
+    self.set_phase(idx, status::Phase::ShuttingDown);
 
     let start_t = Instant::now();
     let deadline = Supervisor::deadline_from_float_seconds(
@@ -346,7 +1240,13 @@ impl Supervisor {
     let env = self.get_proc_script_env("SHUTDOWN", idx);
 
     match self.spec.procs[idx].shutdown {
-      Some(ref shutdown) => match self.run_command(&shutdown.clone(), &env, deadline, None) {
+      Some(ref shutdown) => match self.run_command(
+        &shutdown.clone(),
+        &env,
+        CommandKind::ProcHook(idx),
+        deadline,
+        None,
+      ) {
         Ok(c) => c,
         Err(err) => {
           log::warn!("shutdown script error: {:?}.", err);
@@ -357,9 +1257,6 @@ impl Supervisor {
     };
 
     // Some duplication from run_command, but ownership makes this hard to reuse.
-    let max_delay: u64 = 500;
-    let mut delay: u64 = 10;
-
     loop {
       self.check_signals()?;
 
@@ -373,22 +1270,18 @@ impl Supervisor {
       {
         let p = &mut self.procs[idx];
         match p {
-          Some(c) => match c.try_wait()? {
-            Some(_) => {
+          Some(c) => {
+            if let Some(rc) = c.try_wait()? {
               *p = None;
+              self.record_exit(idx, rc);
               break;
             }
-            None => (),
-          },
+          }
           None => break,
         };
       }
 
-      self.sleep(Duration::from_millis(delay))?;
-      delay += 50;
-      if delay > max_delay {
-        delay = max_delay
-      }
+      self.wait_event(deadline)?;
     }
 
     self.clean_proc(idx)?;
@@ -404,23 +1297,118 @@ impl Supervisor {
     let env = self.get_proc_script_env("CHECK", idx);
     let p = &mut self.procs[idx];
 
-    match p {
-      Some(c) => match c.try_wait()? {
-        None => {
-          let s = &self.spec.procs[idx];
-          match s.check {
-            Some(ref check) => {
-              self.run_command_timeout_secs(&check.clone(), &env, s.check_timeout_seconds, None)
-            }
-            None => Ok(()),
+    let exited = match p {
+      Some(c) => c.try_wait()?,
+      None => return Err(SupervisorError::ProcFailed),
+    };
+
+    if let Some(rc) = exited {
+      *p = None;
+      self.record_exit(idx, rc);
+      self.set_failure(Some(idx), "RUN", "exited", Some(rc));
+      self.set_phase(idx, status::Phase::Stopped);
+      return Err(SupervisorError::ProcFailed);
+    }
+
+    let s = &self.spec.procs[idx];
+    let (check, builtin_checks, timeout) = (
+      s.check.clone(),
+      s.builtin_checks.clone(),
+      s.check_timeout_seconds,
+    );
+    if check.is_some() || !builtin_checks.is_empty() {
+      self.set_phase(idx, status::Phase::Checking);
+    }
+    // The timeout covers the built-in checks and the check command.
+    let deadline = Supervisor::deadline_from_float_seconds(Instant::now(), timeout);
+    let mut result = self.run_builtin_checks(idx, builtin_checks, deadline);
+    if let (Ok(()), Some(check)) = (&result, check) {
+      result = self.run_command(&check, &env, CommandKind::ProcHook(idx), deadline, None);
+    }
+
+    match result {
+      Ok(()) => {
+        self.proc_states[idx].last_check = Some(status::CheckResult {
+          ok: true,
+          time: status::now(),
+        });
+        self.proc_states[idx].last_check_ok = Some(Instant::now());
+        self.set_phase(idx, status::Phase::Running);
+        Ok(())
+      }
+      Err(SupervisorError::ProcFailed) => {
+        self.proc_states[idx].last_check = Some(status::CheckResult {
+          ok: false,
+          time: status::now(),
+        });
+        self.set_phase(idx, status::Phase::Running);
+        Err(SupervisorError::ProcFailed)
+      }
+      Err(e) => Err(e),
+    }
+  }
+
+  // Built-in checks run on their own thread, so signals are still
+  // handled while a check is waiting on the network.
+  fn run_builtin_checks(
+    &mut self,
+    idx: usize,
+    builtin_checks: Vec<specs::BuiltinCheck>,
+    deadline: Option<Instant>,
+  ) -> Result<(), SupervisorError> {
+    if builtin_checks.is_empty() {
+      return Ok(());
+    }
+
+    // Paths are relative to the directory the proc's commands run in.
+    let dir = self.spec.procs[idx]
+      .environment
+      .dir
+      .clone()
+      .or_else(|| self.spec.environment.dir.clone());
+    let (tx, rx) = crossbeam_channel::bounded(1);
+    std::thread::spawn(move || {
+      let result = builtin_checks
+        .iter()
+        .try_for_each(|c| checks::run(c, dir.as_deref(), deadline));
+      let _ = tx.send(result);
+    });
+
+    let timed_out = || deadline.map(|d| Instant::now() >= d).unwrap_or(false);
+    loop {
+      if let Some(sig) = self.deferred_signals.pop_front() {
+        self.handle_signal(sig)?;
+        continue;
+      }
+      let timeout = match deadline {
+        Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+        None => Duration::from_secs(3600),
+      };
+      let err = crossbeam_channel::select! {
+        recv(self.sigrx) -> sig => match sig {
+          Ok(sig) => {
+            self.handle_signal(sig)?;
+            continue;
           }
-        }
-        Some(_) => {
-          *p = None;
-          Err(SupervisorError::ProcFailed)
-        }
-      },
-      None => Err(SupervisorError::ProcFailed),
+          Err(_) => return Err(SupervisorError::Terminated(None)),
+        },
+        recv(rx) -> result => match result {
+          Ok(Ok(())) => return Ok(()),
+          Ok(Err(err)) => err,
+          Err(_) => String::from("check thread exited"),
+        },
+        default(timeout) => {
+          if !timed_out() {
+            continue;
+          }
+          String::from("timed out")
+        },
+      };
+
+      log::warn!("{} check failed: {}.", self.spec.procs[idx].name, err);
+      let reason = if timed_out() { "timeout" } else { "failed" };
+      self.set_failure(Some(idx), "CHECK", reason, None);
+      return Err(SupervisorError::ProcFailed);
     }
   }
 
@@ -428,17 +1416,123 @@ impl Supervisor {
     self.check_signals()?;
 
     log::info!("running {} cleanup.", self.spec.procs[idx].name);
-    if let Some(_) = self.procs[idx] {
+    if self.procs[idx].is_some() {
       panic!("bug, clean without kill.")
     };
 
+    self.kill_cgroup(idx)?;
+    self.kill_orphans(idx)?;
+
     let env = self.get_proc_script_env("CLEANUP", idx);
     let s = &self.spec.procs[idx];
-    match s.cleanup {
+    let result = match s.cleanup {
       Some(ref cleanup) => {
-        self.run_command_timeout_secs(&cleanup.clone(), &env, s.cleanup_timeout_seconds, None)
+        let (cleanup, timeout) = (cleanup.clone(), s.cleanup_timeout_seconds);
+        self.set_phase(idx, status::Phase::Cleaning);
+        self.run_command_timeout_secs(&cleanup, &env, CommandKind::ProcHook(idx), timeout, None)
       }
       None => Ok(()),
+    };
+    self.set_phase(idx, status::Phase::Stopped);
+    result
+  }
+
+  // Anything left in the cgroup once the run command has exited would
+  // otherwise leak across restarts.
+  fn kill_cgroup(&mut self, idx: usize) -> Result<(), SupervisorError> {
+    if let Some(cgroup) = self.proc_states[idx].cgroup.take() {
+      let name = format!("{} cgroup KILL", self.spec.procs[idx].name);
+      let deadline = Instant::now().add(Duration::from_secs(10));
+      let killed = self.track_kill(name, None, Some(deadline), |s| {
+        cgroup.kill(deadline, |timeout| s.wait_kill_event(timeout))
+      });
+      self.proc_states[idx].cgroup = Some(cgroup);
+      if !killed? {
+        return Err(SupervisorError::UnkillableChild);
+      }
+    }
+    Ok(())
+  }
+
+  // Descendants of a proc that escaped its process group are not killed
+  // with it, once they are orphaned they are our children and are killed
+  // here so they don't leak across restarts.
+  fn kill_orphans(&mut self, idx: usize) -> Result<(), SupervisorError> {
+    if !self.subreaper {
+      return Ok(());
+    }
+
+    let name = self.spec.procs[idx].name.clone();
+    self.track_kill(format!("{} orphans KILL", name), None, None, |s| {
+      s.kill_orphans_tracked(idx, &name)
+    })
+  }
+
+  fn kill_orphans_tracked(&mut self, idx: usize, name: &str) -> Result<(), SupervisorError> {
+    // Killing an orphan may orphan its own children, so repeat a few times.
+    for _ in 0..10 {
+      let mut orphans: Vec<i32> = reaper::children()
+        .into_iter()
+        .filter(|pid| !self.is_owned(*pid))
+        .filter(|pid| reaper::service_name(*pid).as_deref() == Some(name))
+        .collect();
+      if orphans.is_empty() {
+        return Ok(());
+      }
+
+      log::info!(
+        "killing {} orphaned process(es) of {}.",
+        orphans.len(),
+        name
+      );
+      for pid in orphans.iter() {
+        unsafe { libc::kill(*pid, libc::SIGTERM) };
+      }
+      let deadline = Supervisor::deadline_from_float_seconds(
+        Instant::now(),
+        self.spec.procs[idx].terminate_timeout_seconds,
+      );
+      self.set_kill_deadline(deadline);
+      if self.wait_orphans(&mut orphans, deadline) {
+        continue;
+      }
+
+      log::warn!(
+        "orphans of {} did not respond to SIGTERM, trying SIGKILL.",
+        name
+      );
+      for pid in orphans.iter() {
+        unsafe { libc::kill(*pid, libc::SIGKILL) };
+      }
+      let deadline = Instant::now().add(Duration::from_secs(10));
+      self.set_kill_deadline(Some(deadline));
+      if !self.wait_orphans(&mut orphans, Some(deadline)) {
+        return Err(SupervisorError::UnkillableChild);
+      }
+    }
+
+    log::warn!("{} keeps leaving orphans behind, giving up on them.", name);
+    Ok(())
+  }
+
+  fn wait_orphans(&mut self, pids: &mut Vec<i32>, deadline: Option<Instant>) -> bool {
+    loop {
+      // Anything that is no longer our child has been reaped already.
+      pids.retain(|pid| unsafe { libc::waitpid(*pid, std::ptr::null_mut(), libc::WNOHANG) } == 0);
+      if pids.is_empty() {
+        return true;
+      }
+      let timeout = match deadline {
+        Some(deadline) => {
+          let now = Instant::now();
+          if now >= deadline {
+            return false;
+          }
+          deadline - now
+        }
+        None => Duration::from_secs(3600),
+      };
+      self.wait_kill_event(timeout);
     }
   }
 
@@ -447,33 +1541,131 @@ impl Supervisor {
 
     log::info!("starting {}.", self.spec.procs[idx].name);
 
-    let env = self.get_proc_script_env("RUN", idx);
+    self.proc_states[idx].starts += 1;
+    self.proc_states[idx].ready = false;
+    self.proc_states[idx].status_text = None;
+    let generation = self.proc_states[idx].starts;
+    self.set_phase(idx, status::Phase::Starting);
+
+    let mut env = self.get_proc_script_env("RUN", idx);
+    if let Some(ref notify) = self.proc_states[idx].notify {
+      env.push(notify.env());
+    }
     let s = self.spec.procs.get(idx).unwrap();
-    let c = Supervisor::spawn_child(&s.run, &env)?;
-    self.procs[idx] = Some(c);
+    if let (Some(ref log_file), None) = (&s.log_file, &self.proc_states[idx].log) {
+      self.proc_states[idx].log = Some(logs::start(log_file, s.log_max_bytes, s.log_keep)?);
+    }
+    if self.proc_states[idx].listeners.is_empty() && !s.listen.is_empty() {
+      let mut listeners = vec![];
+      for l in s.listen.iter() {
+        listeners.push(listen::bind(
+          l.name.as_deref().unwrap_or(&s.name),
+          &l.address,
+        )?);
+      }
+      self.proc_states[idx].listeners = listeners;
+    }
+
+    // The pid of the run command is only known after the fork, so these
+    // are filled in by spawn_child.
+    let mut pid_vars = vec![];
+    if let Some(watchdog) = s.watchdog_seconds {
+      env.push((
+        String::from("WATCHDOG_USEC"),
+        ((watchdog * 1_000_000.0) as u64).to_string(),
+      ));
+      pid_vars.push("WATCHDOG_PID");
+    }
+    let listeners = &self.proc_states[idx].listeners;
+    if !listeners.is_empty() {
+      let names: Vec<&str> = listeners.iter().map(|l| l.name.as_str()).collect();
+      env.push((String::from("LISTEN_FDS"), listeners.len().to_string()));
+      env.push((String::from("LISTEN_FDNAMES"), names.join(":")));
+      pid_vars.push("LISTEN_PID");
+    }
+    if self.spec.procs[idx].notification_fd.is_some() {
+      let (read, write) = logs::pipe()?;
+      notify::watch_notification_fd(
+        &self.spec.procs[idx].name,
+        generation,
+        read,
+        self.sigtx.clone(),
+      );
+      self.proc_states[idx].notification_fd = Some(write);
+    }
+    if let Some(ref notify) = self.proc_states[idx].notify {
+      notify.spawning(generation);
+    }
+    let c = self.spawn_child(&s.run, &env, CommandKind::Run(idx), &pid_vars);
+    if let Some(ref notify) = self.proc_states[idx].notify {
+      notify.spawned(c.as_ref().ok().map(|c| c.id()));
+    }
+    // Only the run command keeps the write end open, so the reader sees
+    // EOF once it exits.
+    self.proc_states[idx].notification_fd = None;
+    self.procs[idx] = Some(c?);
+    self.proc_states[idx].last_watchdog = Some(Instant::now());
+
+    if self.spec.procs[idx].notify || self.spec.procs[idx].notification_fd.is_some() {
+      self.set_phase(idx, status::Phase::Waiting);
+      self.wait_ready(idx)?;
+    }
 
     {
       let env = self.get_proc_script_env("WAIT_STARTED", idx);
       let s = &self.spec.procs[idx];
-      match s.wait_started {
-        Some(ref wait_started) => self.run_command_timeout_secs(
-          &wait_started.clone(),
+      if let Some(ref wait_started) = s.wait_started {
+        let (wait_started, timeout) = (wait_started.clone(), s.wait_started_timeout_seconds);
+        self.set_phase(idx, status::Phase::Waiting);
+        self.run_command_timeout_secs(
+          &wait_started,
           &env,
-          s.wait_started_timeout_seconds,
+          CommandKind::ProcHook(idx),
+          timeout,
           Some(idx),
-        )?,
-        None => (),
+        )?
       }
     }
 
+    self.set_phase(idx, status::Phase::Running);
+
     Ok(())
   }
 
-  fn kill_all_procs(&mut self) -> Result<(), SupervisorError> {
-    for i in (0..self.procs.len()).rev() {
-      self.kill_proc(i)?;
+  // Procs in notify mode are started once they send READY=1, or write
+  // to their notification fd.
+  fn wait_ready(&mut self, idx: usize) -> Result<(), SupervisorError> {
+    let deadline = Supervisor::deadline_from_float_seconds(
+      Instant::now(),
+      self.spec.procs[idx].wait_started_timeout_seconds,
+    );
+    loop {
+      if self.proc_states[idx].ready {
+        return Ok(());
+      }
+
+      let exited = match self.procs[idx] {
+        Some(ref mut c) => c.try_wait()?,
+        None => None,
+      };
+      if let Some(rc) = exited {
+        self.set_failure(Some(idx), "RUN", "exited", Some(rc));
+        return Err(SupervisorError::ProcFailed);
+      }
+
+      if let Some(deadline) = deadline {
+        if Instant::now() > deadline {
+          log::warn!(
+            "{} did not notify readiness in time.",
+            self.spec.procs[idx].name
+          );
+          self.set_failure(Some(idx), "WAIT_STARTED", "timeout", None);
+          return Err(SupervisorError::ProcFailed);
+        }
+      }
+
+      self.wait_event(deadline)?;
     }
-    Ok(())
   }
 
   fn kill_all_procs_ignore_errors(&mut self) {
@@ -491,13 +1683,69 @@ impl Supervisor {
     Ok(())
   }
 
-  fn restart_all_procs(&mut self) -> Result<(), SupervisorError> {
-    log::info!("(re)starting all procs.");
+  fn proc_error(&mut self, idx: usize, e: SupervisorError) -> SupervisorError {
+    // Requested restarts have already chosen which proc to restart.
+    if let SupervisorError::RestartRequested = e {
+      return e;
+    }
+    self.failed_proc = Some(idx);
+    e
+  }
 
-    self.kill_all_procs()?;
+  fn should_restart_proc(&self, idx: usize, failed: Option<usize>) -> bool {
+    // Procs that are not running always need to be started.
+    if self.procs[idx].is_none() {
+      return true;
+    }
 
-    for i in 0..self.procs.len() {
-      self.start_proc(i)?;
+    // A reload only starts the procs it stopped.
+    if self.reloading {
+      return false;
+    }
+
+    match (self.spec.restart_strategy, failed) {
+      (_, None) | (specs::RestartStrategy::OneForAll, _) => true,
+      (specs::RestartStrategy::OneForOne, Some(failed)) => idx == failed,
+      (specs::RestartStrategy::RestForOne, Some(failed)) => idx >= failed,
+    }
+  }
+
+  fn restart_procs(&mut self, delay: Duration) -> Result<(), SupervisorError> {
+    let failed = self.failed_proc.take();
+    let restart: Vec<usize> = (0..self.procs.len())
+      .filter(|i| self.should_restart_proc(*i, failed))
+      .collect();
+    self.reloading = false;
+
+    if restart.len() == self.procs.len() {
+      log::info!("(re)starting all procs.");
+    } else if !restart.is_empty() {
+      let names: Vec<&str> = restart
+        .iter()
+        .map(|i| self.spec.procs[*i].name.as_str())
+        .collect();
+      log::info!("(re)starting {}.", names.join(", "));
+    }
+
+    // Cleanup happens in reverse order, startup in order.
+    for i in restart.iter().rev() {
+      if let Err(e) = self.kill_proc(*i) {
+        return Err(self.proc_error(*i, e));
+      }
+    }
+
+    if delay > Duration::from_secs(0) {
+      log::info!("waiting {:.1}s before restarting.", delay.as_secs_f64());
+      let deadline = Instant::now().add(delay);
+      while Instant::now() < deadline {
+        self.wait_event(Some(deadline))?;
+      }
+    }
+
+    for i in restart.iter() {
+      if let Err(e) = self.start_proc(*i) {
+        return Err(self.proc_error(*i, e));
+      }
     }
 
     Ok(())
@@ -505,29 +1753,177 @@ impl Supervisor {
 
   fn check_all_procs(&mut self) -> Result<(), SupervisorError> {
     for i in 0..self.procs.len() {
-      self.check_proc(i)?;
+      if let Err(e) = self.check_proc(i) {
+        return Err(self.proc_error(i, e));
+      }
+    }
+
+    Ok(())
+  }
+
+  fn check_exited_procs(&mut self) -> Result<(), SupervisorError> {
+    for i in 0..self.procs.len() {
+      let exited = match self.procs[i] {
+        Some(ref mut c) => c.try_wait()?,
+        None => None,
+      };
+      if let Some(rc) = exited {
+        self.procs[i] = None;
+        self.record_exit(i, rc);
+        self.set_failure(Some(i), "RUN", "exited", Some(rc));
+        self.set_phase(i, status::Phase::Stopped);
+        return Err(self.proc_error(i, SupervisorError::ProcFailed));
+      }
+    }
+
+    Ok(())
+  }
+
+  // A proc that stops sending WATCHDOG=1 is assumed to be hung.
+  fn check_watchdogs(&mut self) -> Result<(), SupervisorError> {
+    let now = Instant::now();
+    for i in 0..self.procs.len() {
+      if let Some(deadline) = self.watchdog_deadline(i) {
+        if now >= deadline {
+          log::warn!("{} missed its watchdog deadline.", self.spec.procs[i].name);
+          self.set_failure(Some(i), "WATCHDOG", "timeout", None);
+          return Err(self.proc_error(i, SupervisorError::ProcFailed));
+        }
+      }
     }
 
     Ok(())
   }
 
-  fn supervise(&mut self, num_restarts: u128) -> SupervisorError {
+  fn watchdog_deadline(&self, idx: usize) -> Option<Instant> {
+    self.procs[idx].as_ref()?;
+    let watchdog = self.spec.procs[idx].watchdog_seconds?;
+    let last = self.proc_states[idx].last_watchdog?;
+    Some(last.add(Duration::from_millis((watchdog * 1000.0) as u64)))
+  }
+
+  // Procs before the first changed proc keep running, the rest are shut
+  // down in reverse order and replaced with the new specs, to be started
+  // in order as with a rest_for_one restart.
+  fn reload(&mut self, mut spec: specs::SupervisorSpec) -> Result<(), SupervisorError> {
+    let mut keep = self
+      .spec
+      .procs
+      .iter()
+      .zip(spec.procs.iter())
+      .take_while(|(old, new)| old == new)
+      .count();
+    // Every proc inherits the supervisor environment.
+    if spec.environment != self.spec.environment {
+      keep = 0;
+    }
+
+    for i in (keep..self.procs.len()).rev() {
+      if let Err(e) = self.shutdown_proc(i) {
+        return Err(self.proc_error(i, e));
+      }
+    }
+
+    if spec.control_socket != self.spec.control_socket
+      || spec.status_file != self.spec.status_file
+      || spec.status_format != self.spec.status_format
+      || spec.output != self.spec.output
+      || spec.output_color != self.spec.output_color
+      || spec.cgroup_parent != self.spec.cgroup_parent
+    {
+      log::warn!(
+        "changes to -control-socket, -status-file, -status-format, -output, -output-color \
+         and -cgroup-parent only take effect when orderly is restarted."
+      );
+    }
+    spec.control_socket = self.spec.control_socket.take();
+    spec.status_file = self.spec.status_file.take();
+    spec.status_format = self.spec.status_format;
+    spec.output = self.spec.output;
+    spec.output_color = self.spec.output_color;
+    spec.cgroup_parent = self.spec.cgroup_parent.take();
+
+    for (s, state) in self.spec.procs[keep..]
+      .iter()
+      .zip(self.proc_states[keep..].iter())
+    {
+      if spec.procs.iter().any(|p| p.name == s.name) {
+        continue;
+      }
+      log::info!("removed {}.", s.name);
+      if let Some(ref cgroup) = state.cgroup {
+        if let Err(err) = cgroup.remove() {
+          log::warn!("unable to remove cgroup of {}: {}.", s.name, err);
+        }
+      }
+    }
+
+    // Listeners of procs that still listen on the same addresses are kept
+    // open, so connections queue up while they restart.
+    let mut listeners = std::collections::HashMap::new();
+    for (s, state) in self.spec.procs[keep..]
+      .iter()
+      .zip(self.proc_states[keep..].iter_mut())
+    {
+      listeners.insert(
+        s.name.clone(),
+        (s.listen.clone(), std::mem::take(&mut state.listeners)),
+      );
+    }
+
+    self.procs.truncate(keep);
+    self.proc_states.truncate(keep);
+    for s in spec.procs[keep..].iter() {
+      let mut state = Supervisor::new_proc_state(&spec, s, &self.sigtx)?;
+      if let Some((listen, l)) = listeners.remove(&s.name) {
+        if listen == s.listen {
+          state.listeners = l;
+        }
+      }
+      self.procs.push(None);
+      self.proc_states.push(state);
+    }
+
+    if spec.max_restart_tokens != self.spec.max_restart_tokens
+      || spec.restart_tokens_per_second != self.spec.restart_tokens_per_second
+    {
+      self.rate_limiter = RateLimiter::new(spec.max_restart_tokens, spec.restart_tokens_per_second);
+    }
+    self.backoff = Backoff::new(&spec);
+    self.spec = spec;
+    self.failed_proc = None;
+    self.reloading = true;
+
+    Ok(())
+  }
+
+  fn supervise(&mut self, restart_requested: bool) -> SupervisorError {
+    self.running = false;
+    let ran_for = self
+      .started_at
+      .take()
+      .map(|t| t.elapsed())
+      .unwrap_or_default();
+
     if self.first_start {
-      if let Err(e) = self.write_status_file("STARTING\n") {
+      if let Err(e) = self.write_status_file() {
         return e;
       }
     }
 
-    if !self.rate_limiter.take() {
+    // Restarts requested via the control socket are not failures.
+    if !restart_requested && !self.rate_limiter.take() {
       return SupervisorError::RestartLimitReached;
     }
 
-    if num_restarts > 0 {
-      if let Some(ref restart) = self.spec.restart {
+    if self.num_restarts > 0 && !restart_requested {
+      if let Some(restart) = self.spec.restart.clone() {
+        let env = self.get_failure_script_env("RESTART");
         if let Err(e) = self.run_command(
-          &restart.clone(),
-          &Supervisor::get_supervisor_script_env("RESTART"),
-          Supervisor::deadline_from_float_seconds(Instant::now(), self.spec.failure_timeout),
+          &restart,
+          &env,
+          CommandKind::SupervisorHook,
+          Supervisor::deadline_from_float_seconds(Instant::now(), self.spec.restart_timeout),
           None,
         ) {
           log::error!("error running restart lifecycle hook: {:?}.", e);
@@ -535,22 +1931,41 @@ impl Supervisor {
       }
     }
 
-    match self.restart_all_procs() {
+    if let Some(spec) = self.pending_spec.take() {
+      if let Err(e) = self.reload(spec) {
+        return e;
+      }
+    }
+
+    let delay = if self.num_restarts > 0 && !restart_requested {
+      self.backoff.next_delay(ran_for)
+    } else {
+      Duration::from_secs(0)
+    };
+
+    match self.restart_procs(delay) {
       Ok(()) => (),
       Err(e) => return e,
     };
 
+    self.running = true;
+    self.started_at = Some(Instant::now());
+    self.restart_reason = None;
+    self.failure = None;
+    self.last_error = None;
+
     if self.first_start {
       self.first_start = false;
 
-      if let Err(e) = self.write_status_file("RUNNING\n") {
+      if let Err(e) = self.write_status_file() {
         return e;
       }
 
       if let Some(ref start_complete) = self.spec.start_complete {
         if let Err(e) = self.run_command(
           &start_complete.clone(),
-          &Supervisor::get_supervisor_script_env("START_COMPLETE"),
+          &self.get_supervisor_script_env("START_COMPLETE"),
+          CommandKind::SupervisorHook,
           Supervisor::deadline_from_float_seconds(Instant::now(), self.spec.start_complete_timeout),
           None,
         ) {
@@ -559,15 +1974,32 @@ impl Supervisor {
       }
     }
 
+    let check_delay = Duration::from_millis((self.spec.check_delay_seconds * 1000.0) as u64);
+    let mut next_check = Instant::now();
+
     loop {
-      match self.check_all_procs() {
-        Ok(()) => match self.sleep(Duration::from_millis(
-          (self.spec.check_delay_seconds * 1000.0) as u64,
-        )) {
-          Ok(()) => continue,
-          Err(e) => return e,
-        },
-        Err(e) => return e,
+      if Instant::now() >= next_check {
+        if let Err(e) = self.check_all_procs() {
+          return e;
+        }
+        next_check = Instant::now().add(check_delay);
+      }
+
+      // Exits are checked before every wait, so a wakeup consumed
+      // elsewhere can never hide a crash until the next health check.
+      if let Err(e) = self.check_exited_procs() {
+        return e;
+      }
+
+      if let Err(e) = self.check_watchdogs() {
+        return e;
+      }
+
+      let wake = (0..self.procs.len())
+        .filter_map(|i| self.watchdog_deadline(i))
+        .fold(next_check, |wake, deadline| wake.min(deadline));
+      if let Err(e) = self.wait_event(Some(wake)) {
+        return e;
       }
     }
   }
@@ -575,43 +2007,54 @@ impl Supervisor {
   fn supervise_forever(&mut self) {
     let rc: i32;
 
-    let mut num_restarts: u128 = 0;
+    let mut restart_requested = false;
 
     loop {
-      match self.supervise(num_restarts) {
+      match self.supervise(restart_requested) {
+        SupervisorError::RestartRequested | SupervisorError::ReloadRequested => {
+          restart_requested = true;
+        }
         e @ SupervisorError::IOError(_) | e @ SupervisorError::ProcFailed => {
-          num_restarts = num_restarts + 1;
+          restart_requested = false;
+          self.num_restarts += 1;
+          self.last_error = Some(e.name());
           log::warn!(
             "supervisor encountered an error: {:?} (restarts={}).",
             e,
-            num_restarts
+            self.num_restarts
           );
         }
         SupervisorError::Shutdown => {
           log::info!("supervisor shutting down gracefully.");
-          match self.shutdown_all_procs() {
-            Ok(()) => (),
+          self.stopping = true;
+          rc = match self.shutdown_all_procs() {
+            Ok(()) => 0,
             Err(e) => {
               log::error!("unable shutdown child procs, killing instead: {:?}.", e);
               self.kill_all_procs_ignore_errors();
+              1
             }
-          }
-          rc = 0;
+          };
           break;
         }
-        e @ SupervisorError::Terminated
+        e @ SupervisorError::Terminated(_)
         | e @ SupervisorError::RestartLimitReached
         | e @ SupervisorError::UnkillableChild => {
           log::error!(
             "supervisor unable to continue: {:?} - shutting down brutally.",
             e
           );
+          self.stopping = true;
+          self.last_error = Some(e.name());
+          // Taken before killing, as failures while killing are not the cause.
+          let env = self.get_failure_script_env("FAILURE");
           self.kill_all_procs_ignore_errors();
 
           if let Some(ref failure) = self.spec.failure {
             if let Err(e) = self.run_command(
               &failure.clone(),
-              &Supervisor::get_supervisor_script_env("FAILURE"),
+              &env,
+              CommandKind::SupervisorHook,
               Supervisor::deadline_from_float_seconds(Instant::now(), self.spec.failure_timeout),
               None,
             ) {
@@ -619,7 +2062,12 @@ impl Supervisor {
             }
           }
 
-          rc = 1;
+          // As pid 1 the exit code is what a container runtime reports,
+          // so make a signal termination visible there.
+          rc = match e {
+            SupervisorError::Terminated(Some(signo)) if std::process::id() == 1 => 128 + signo,
+            _ => 1,
+          };
           break;
         }
       }
@@ -631,6 +2079,26 @@ impl Supervisor {
       }
     }
 
+    if let Some(ref path) = self.spec.control_socket {
+      if let Err(err) = std::fs::remove_file(path) {
+        log::warn!("error removing control socket: {}.", err);
+      }
+    }
+
+    for state in self.proc_states.iter_mut() {
+      if let Some(ref cgroup) = state.cgroup {
+        if let Err(err) = cgroup.remove() {
+          log::warn!("error removing cgroup: {}.", err);
+        }
+      }
+      // Dropping a listener removes its unix socket file.
+      state.listeners.clear();
+    }
+
+    if let Some(ref prefixer) = self.prefixer {
+      prefixer.flush(Duration::from_secs(1));
+    }
+
     std::process::exit(rc);
   }
 }
@@ -645,33 +2113,93 @@ fn version() {
   std::process::exit(0);
 }
 
+fn lookup_env<'a>(env: &'a [(String, String)], key: &str) -> Option<&'a str> {
+  env.iter().find(|v| v.0 == key).map(|v| v.1.as_str())
+}
+
 fn die(s: &str) -> ! {
   log::error!("{}", s);
   std::process::exit(1);
 }
 
-fn main() {
-  simple_logger::init().unwrap();
-
-  let args: Vec<String> = std::env::args().collect();
-  let mut arg_idx = 1;
+const COMMAND_FLAGS: [&str; 9] = [
+  "-all-commands",
+  "-start-complete",
+  "-on-restart",
+  "-on-failure",
+  "-run",
+  "-wait-started",
+  "-check",
+  "-shutdown",
+  "-cleanup",
+];
+
+// Every command flag takes a program, or with an -argv suffix a program
+// and its arguments up to a ';', or with a -sh suffix a script for
+// /bin/sh -c.
+#[derive(Clone, Copy, PartialEq)]
+enum CommandForm {
+  Program,
+  Argv,
+  Shell,
+}
 
-  let mut supervisor_spec_builder = specs::SupervisorSpecBuilder::new();
-  let mut proc_spec_builder = specs::ProcSpecBuilder::new();
+fn command_flag(arg: &str) -> (&str, CommandForm) {
+  let split = [("-argv", CommandForm::Argv), ("-sh", CommandForm::Shell)]
+    .iter()
+    .find_map(|(suffix, form)| arg.strip_suffix(suffix).map(|flag| (flag, *form)));
+  match split {
+    Some((flag, form)) if COMMAND_FLAGS.contains(&flag) => (flag, form),
+    _ => (arg, CommandForm::Program),
+  }
+}
 
-  for a in &args {
-    if a == "--" {
-      break;
+// The end of a '--' separated section of process flags, skipping argv
+// flags as their arguments may contain '--'.
+fn section_end(args: &[String], start: usize) -> usize {
+  let mut idx = start;
+  while idx < args.len() {
+    if args[idx] == "--" {
+      return idx;
     }
-
-    if a == "-h" || a == "-help" || a == "--help" {
-      usage();
+    if command_flag(&args[idx]).1 == CommandForm::Argv {
+      if let Some(n) = args[idx..].iter().position(|a| a == ";") {
+        idx += n;
+      }
     }
+    idx += 1;
+  }
+  args.len()
+}
 
-    if a == "-version" || a == "--version" {
-      version();
+// Builds the spec from the config file and command line flags, this
+// runs again on reload to pick up config file changes. Invalid flags
+// exit, as the command line cannot change after startup.
+fn parse_spec(args: &[String]) -> Result<specs::SupervisorSpec, String> {
+  let mut arg_idx = 1;
+
+  let mut supervisor_spec_builder = specs::SupervisorSpecBuilder::new();
+  let mut proc_spec_builders: Vec<specs::ProcSpecBuilder> = vec![];
+
+  // The config file is loaded before any other flags, so command line
+  // flags always override config values regardless of their position.
+  {
+    let mut idx = 1;
+    while idx < args.len() && args[idx] != "--" {
+      if args[idx] == "-config" {
+        let path = args
+          .get(idx + 1)
+          .ok_or_else(|| String::from("-config expected an argument."))?;
+        let cfg =
+          config::load(path).map_err(|e| format!("unable to load config {}: {}.", path, e))?;
+        cfg.apply(&mut supervisor_spec_builder);
+        proc_spec_builders = cfg.proc_spec_builders();
+        idx += 1;
+      }
+      idx += 1;
     }
   }
+  let config_procs = proc_spec_builders.len();
 
   macro_rules! float_arg {
     () => {{
@@ -689,6 +2217,56 @@ fn main() {
     }};
   }
 
+  macro_rules! finite_arg {
+    () => {{
+      let arg = float_arg!();
+      if !arg.is_finite() {
+        die(format!("{} is not a finite number.", arg).as_ref());
+      }
+      arg
+    }};
+  }
+
+  macro_rules! uint_arg {
+    () => {{
+      let arg = args
+        .get(arg_idx + 1)
+        .unwrap_or_else(|| die(format!("{} expects a number.", args[arg_idx]).as_ref()));
+
+      let arg = arg
+        .parse::<u64>()
+        .unwrap_or_else(|_e| die(format!("{} is not a valid u64.", arg).as_ref()));
+
+      arg_idx += 2;
+
+      arg
+    }};
+  }
+
+  macro_rules! int_arg {
+    () => {{
+      let arg = args
+        .get(arg_idx + 1)
+        .unwrap_or_else(|| die(format!("{} expects a number.", args[arg_idx]).as_ref()));
+
+      let arg = arg
+        .parse::<i32>()
+        .unwrap_or_else(|_e| die(format!("{} is not a valid i32.", arg).as_ref()));
+
+      arg_idx += 2;
+
+      arg
+    }};
+  }
+
+  macro_rules! rlimit_arg {
+    () => {{
+      let arg = string_arg!();
+      specs::parse_rlimit(&arg)
+        .unwrap_or_else(|| die(format!("{} is not a valid rlimit.", arg).as_ref()))
+    }};
+  }
+
   macro_rules! string_arg {
     () => {{
       let arg = args
@@ -699,156 +2277,485 @@ fn main() {
     }};
   }
 
+  macro_rules! env_arg {
+    () => {{
+      let arg = string_arg!();
+      match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => (key.to_string(), value.to_string()),
+        _ => die(format!("{} is not a valid KEY=VALUE.", arg).as_ref()),
+      }
+    }};
+  }
+
+  macro_rules! command_arg {
+    ($form:expr) => {{
+      match $form {
+        CommandForm::Program => vec![string_arg!()],
+        CommandForm::Shell => vec!["/bin/sh".to_string(), "-c".to_string(), string_arg!()],
+        CommandForm::Argv => {
+          let start = arg_idx + 1;
+          let end = args[start..]
+            .iter()
+            .position(|a| a == ";")
+            .map(|n| start + n)
+            .unwrap_or_else(|| {
+              die(format!("{} expects arguments terminated by ';'.", args[arg_idx]).as_ref())
+            });
+          if end == start {
+            die(format!("{} expects a command.", args[arg_idx]).as_ref());
+          }
+          arg_idx = end + 1;
+          args[start..end].to_vec()
+        }
+      }
+    }};
+  }
+
+  let mut more_procs = false;
+
   while arg_idx < args.len() {
-    match args[arg_idx].as_ref() {
+    let (flag, form) = command_flag(&args[arg_idx]);
+    match flag {
+      "-config" => {
+        // Already loaded above.
+        arg_idx += 2;
+      }
       "-restart-tokens-per-second" => {
         supervisor_spec_builder.set_restart_tokens_per_second(float_arg!());
       }
       "-check-delay" => {
         supervisor_spec_builder.set_check_delay_seconds(float_arg!());
       }
+      "-restart-strategy" => {
+        let strategy = string_arg!();
+        match specs::RestartStrategy::parse(&strategy) {
+          Some(strategy) => supervisor_spec_builder.set_restart_strategy(strategy),
+          None => die(format!("{} is not a valid restart strategy.", strategy).as_ref()),
+        }
+      }
       "-max-restart-tokens" => {
         supervisor_spec_builder.set_max_restart_tokens(float_arg!());
       }
+      "-restart-backoff" => {
+        supervisor_spec_builder.set_restart_backoff_seconds(finite_arg!());
+      }
+      "-restart-backoff-max" => {
+        supervisor_spec_builder.set_restart_backoff_max_seconds(finite_arg!());
+      }
+      "-restart-backoff-multiplier" => {
+        supervisor_spec_builder.set_restart_backoff_multiplier(finite_arg!());
+      }
+      "-restart-backoff-jitter" => {
+        supervisor_spec_builder.set_restart_backoff_jitter(finite_arg!());
+      }
+      "-restart-backoff-reset" => {
+        supervisor_spec_builder.set_restart_backoff_reset_seconds(finite_arg!());
+      }
       "-status-file" => {
         supervisor_spec_builder.set_status_file(string_arg!());
       }
+      "-status-format" => {
+        let format = string_arg!();
+        match specs::StatusFormat::parse(&format) {
+          Some(format) => supervisor_spec_builder.set_status_format(format),
+          None => die(format!("{} is not a valid status format.", format).as_ref()),
+        }
+      }
+      "-control-socket" => {
+        supervisor_spec_builder.set_control_socket(string_arg!());
+      }
+      "-output" => {
+        let mode = string_arg!();
+        match specs::OutputMode::parse(&mode) {
+          Some(mode) => supervisor_spec_builder.set_output(mode),
+          None => die(format!("{} is not a valid output mode.", mode).as_ref()),
+        }
+      }
+      "-output-color" => {
+        let mode = string_arg!();
+        match specs::ColorMode::parse(&mode) {
+          Some(mode) => supervisor_spec_builder.set_output_color(mode),
+          None => die(format!("{} is not a valid color mode.", mode).as_ref()),
+        }
+      }
+      "-cgroup-parent" => {
+        supervisor_spec_builder.set_cgroup_parent(string_arg!());
+      }
+      "-dir" => {
+        supervisor_spec_builder.environment().set_dir(string_arg!());
+      }
+      "-env" => {
+        let (key, value) = env_arg!();
+        supervisor_spec_builder.environment().add_env(key, value);
+      }
+      "-env-file" => {
+        supervisor_spec_builder
+          .environment()
+          .add_env_file(string_arg!());
+      }
+      "-clear-env" => {
+        supervisor_spec_builder.environment().set_clear_env(true);
+        arg_idx += 1;
+      }
+      "-pass-env" => {
+        supervisor_spec_builder
+          .environment()
+          .add_pass_env(string_arg!());
+      }
       "-start-complete" => {
-        supervisor_spec_builder.set_start_complete(string_arg!());
+        supervisor_spec_builder.set_start_complete(command_arg!(form));
       }
       "-start-complete-timeout" => {
         supervisor_spec_builder.set_start_complete_timeout(float_arg!());
       }
       "-on-restart" => {
-        supervisor_spec_builder.set_restart(string_arg!());
+        supervisor_spec_builder.set_restart(command_arg!(form));
       }
       "-on-restart-timeout" => {
         supervisor_spec_builder.set_restart_timeout(float_arg!());
       }
       "-on-failure" => {
-        supervisor_spec_builder.set_failure(string_arg!());
+        supervisor_spec_builder.set_failure(command_arg!(form));
       }
       "-on-failure-timeout" => {
         supervisor_spec_builder.set_failure_timeout(float_arg!());
       }
       "-all-commands" => {
-        let all = args
-          .get(arg_idx + 1)
-          .unwrap_or_else(|| die("-all-commands expected an argument."));
+        let all = command_arg!(form);
         supervisor_spec_builder.set_start_complete(all.clone());
         supervisor_spec_builder.set_restart(all.clone());
-        supervisor_spec_builder.set_failure(all.clone());
-        arg_idx += 2;
+        supervisor_spec_builder.set_failure(all);
       }
       "--" => {
         arg_idx += 1;
+        more_procs = true;
         break;
       }
-      unknown => die(format!("unknown argument: {}.", unknown).as_ref()),
+      _ => die(format!("unknown argument: {}.", args[arg_idx]).as_ref()),
     }
   }
 
-  while arg_idx < args.len() {
-    match args[arg_idx].as_ref() {
-      "-name" => {
-        proc_spec_builder.set_name(string_arg!());
-      }
-      "-run" => {
-        proc_spec_builder.set_run(string_arg!());
-      }
-      "-check" => {
-        proc_spec_builder.set_check(string_arg!());
-      }
-      "-check-timeout" => {
-        proc_spec_builder.set_check_timeout_seconds(float_arg!());
-      }
-      "-wait-started" => {
-        proc_spec_builder.set_wait_started(string_arg!());
-      }
-      "-wait-started-timeout" => {
-        proc_spec_builder.set_wait_started_timeout_seconds(float_arg!());
-      }
-      "-cleanup" => {
-        proc_spec_builder.set_cleanup(string_arg!());
-      }
-      "-cleanup-timeout" => {
-        proc_spec_builder.set_cleanup_timeout_seconds(float_arg!());
-      }
-      "-shutdown" => {
-        proc_spec_builder.set_shutdown(string_arg!());
+  while more_procs {
+    let section_end = section_end(args, arg_idx);
+    let section_start = arg_idx;
+
+    // A process with the same name as one from the config file
+    // overrides that process in place, otherwise it is appended. The
+    // name is only known once the section is parsed, so an override
+    // parses the section again on top of the config file's process.
+    let mut existing = None;
+    let mut proc_spec_builder = specs::ProcSpecBuilder::new();
+    loop {
+      arg_idx = section_start;
+      while arg_idx < section_end {
+        let (flag, form) = command_flag(&args[arg_idx]);
+        match flag {
+          "-name" => {
+            proc_spec_builder.set_name(string_arg!());
+          }
+          "-run" => {
+            proc_spec_builder.set_run(command_arg!(form));
+          }
+          "-check" => {
+            proc_spec_builder.set_check(command_arg!(form));
+          }
+          "-check-timeout" => {
+            proc_spec_builder.set_check_timeout_seconds(float_arg!());
+          }
+          "-check-tcp" => {
+            proc_spec_builder.set_check_tcp(string_arg!());
+          }
+          "-check-http" => {
+            proc_spec_builder.set_check_http(string_arg!());
+          }
+          "-check-http-status" => {
+            proc_spec_builder.set_check_http_status(uint_arg!());
+          }
+          "-check-unix" => {
+            proc_spec_builder.set_check_unix(string_arg!());
+          }
+          "-check-file" => {
+            proc_spec_builder.set_check_file(string_arg!());
+          }
+          "-check-file-max-age" => {
+            proc_spec_builder.set_check_file_max_age_seconds(float_arg!());
+          }
+          "-wait-started" => {
+            proc_spec_builder.set_wait_started(command_arg!(form));
+          }
+          "-wait-started-timeout" => {
+            proc_spec_builder.set_wait_started_timeout_seconds(float_arg!());
+          }
+          "-notify" => {
+            proc_spec_builder.set_notify(true);
+            arg_idx += 1;
+          }
+          "-watchdog" => {
+            proc_spec_builder.set_watchdog_seconds(float_arg!());
+          }
+          "-notification-fd" => {
+            proc_spec_builder.set_notification_fd(int_arg!());
+          }
+          "-listen" => {
+            let listen = string_arg!();
+            match specs::ListenSpec::parse(&listen) {
+              Some(listen) => proc_spec_builder.add_listen(listen),
+              None => die(format!("{} is not a valid listen address.", listen).as_ref()),
+            }
+          }
+          "-cleanup" => {
+            proc_spec_builder.set_cleanup(command_arg!(form));
+          }
+          "-cleanup-timeout" => {
+            proc_spec_builder.set_cleanup_timeout_seconds(float_arg!());
+          }
+          "-shutdown" => {
+            proc_spec_builder.set_shutdown(command_arg!(form));
+          }
+          "-shutdown-timeout" => {
+            proc_spec_builder.set_shutdown_timeout_seconds(float_arg!());
+          }
+          "-terminate-timeout" => {
+            proc_spec_builder.set_terminate_timeout_seconds(float_arg!());
+          }
+          "-log-file" => {
+            proc_spec_builder.set_log_file(string_arg!());
+          }
+          "-log-dir" => {
+            proc_spec_builder.set_log_dir(string_arg!());
+          }
+          "-log-max-size" => {
+            proc_spec_builder.set_log_max_bytes(uint_arg!());
+          }
+          "-memory-max" => {
+            proc_spec_builder.set_memory_max(uint_arg!());
+          }
+          "-cpu-max" => {
+            proc_spec_builder.set_cpu_max(float_arg!());
+          }
+          "-pids-max" => {
+            proc_spec_builder.set_pids_max(uint_arg!());
+          }
+          "-rlimit-nofile" => {
+            proc_spec_builder.set_rlimit_nofile(rlimit_arg!());
+          }
+          "-rlimit-core" => {
+            proc_spec_builder.set_rlimit_core(rlimit_arg!());
+          }
+          "-rlimit-as" => {
+            proc_spec_builder.set_rlimit_as(rlimit_arg!());
+          }
+          "-rlimit-nproc" => {
+            proc_spec_builder.set_rlimit_nproc(rlimit_arg!());
+          }
+          "-nice" => {
+            proc_spec_builder.set_nice(int_arg!());
+          }
+          "-ioprio" => {
+            let ioprio = string_arg!();
+            match specs::IoPrio::parse(&ioprio) {
+              Some(ioprio) => proc_spec_builder.set_ioprio(ioprio),
+              None => die(format!("{} is not a valid ioprio.", ioprio).as_ref()),
+            }
+          }
+          "-oom-score-adj" => {
+            proc_spec_builder.set_oom_score_adj(int_arg!());
+          }
+          "-cpu-affinity" => {
+            let cpus = string_arg!();
+            match specs::parse_cpu_list(&cpus) {
+              Some(cpus) => proc_spec_builder.set_cpu_affinity(cpus),
+              None => die(format!("{} is not a valid cpu list.", cpus).as_ref()),
+            }
+          }
+          "-user" => {
+            proc_spec_builder.set_user(string_arg!());
+          }
+          "-group" => {
+            proc_spec_builder.set_group(string_arg!());
+          }
+          "-groups" => {
+            let groups = string_arg!();
+            proc_spec_builder.set_groups(
+              groups
+                .split(',')
+                .filter(|g| !g.is_empty())
+                .map(|g| g.to_string())
+                .collect(),
+            );
+          }
+          "-hook-user" => {
+            proc_spec_builder.set_hook_user(string_arg!());
+          }
+          "-hook-group" => {
+            proc_spec_builder.set_hook_group(string_arg!());
+          }
+          "-dir" => {
+            proc_spec_builder.environment().set_dir(string_arg!());
+          }
+          "-env" => {
+            let (key, value) = env_arg!();
+            proc_spec_builder.environment().add_env(key, value);
+          }
+          "-env-file" => {
+            proc_spec_builder.environment().add_env_file(string_arg!());
+          }
+          "-clear-env" => {
+            proc_spec_builder.environment().set_clear_env(true);
+            arg_idx += 1;
+          }
+          "-pass-env" => {
+            proc_spec_builder.environment().add_pass_env(string_arg!());
+          }
+          "-log-keep" => {
+            proc_spec_builder.set_log_keep(uint_arg!());
+          }
+          "-all-commands" => {
+            let all = command_arg!(form);
+            proc_spec_builder.set_run(all.clone());
+            proc_spec_builder.set_check(all.clone());
+            proc_spec_builder.set_wait_started(all.clone());
+            proc_spec_builder.set_shutdown(all.clone());
+            proc_spec_builder.set_cleanup(all);
+          }
+          _ => die(format!("unknown process spec argument: {}.", args[arg_idx]).as_ref()),
+        }
       }
-      "-shutdown-timeout" => {
-        proc_spec_builder.set_shutdown_timeout_seconds(float_arg!());
+      if existing.is_some() {
+        break;
       }
-      "-terminate-timeout" => {
-        proc_spec_builder.set_terminate_timeout_seconds(float_arg!());
+      existing = proc_spec_builder.name().and_then(|name| {
+        proc_spec_builders[..config_procs]
+          .iter()
+          .position(|b| b.name() == Some(name))
+      });
+      match existing {
+        Some(idx) => {
+          proc_spec_builder =
+            std::mem::replace(&mut proc_spec_builders[idx], specs::ProcSpecBuilder::new())
+        }
+        None => break,
       }
-      "-all-commands" => {
-        let all = args
-          .get(arg_idx + 1)
-          .unwrap_or_else(|| die("-all-commands expected an argument."));
-        proc_spec_builder.set_run(all.clone());
-        proc_spec_builder.set_check(all.clone());
-        proc_spec_builder.set_wait_started(all.clone());
-        proc_spec_builder.set_shutdown(all.clone());
-        proc_spec_builder.set_cleanup(all.clone());
-        arg_idx += 2;
+    }
+
+    match existing {
+      Some(idx) => proc_spec_builders[idx] = proc_spec_builder,
+      None => proc_spec_builders.push(proc_spec_builder),
+    }
+
+    more_procs = section_end < args.len();
+    arg_idx = section_end + 1;
+  }
+
+  if proc_spec_builders.is_empty() {
+    proc_spec_builders.push(specs::ProcSpecBuilder::new());
+  }
+
+  for proc_spec_builder in proc_spec_builders {
+    match proc_spec_builder.build() {
+      Ok(spec) => supervisor_spec_builder.add_proc_spec(spec),
+      Err(specs::SpecError::MissingField(f)) => {
+        return Err(format!("proc spec missing field '{}'", f))
       }
-      "--" => {
-        match proc_spec_builder.build() {
-          Ok(spec) => {
-            supervisor_spec_builder.add_proc_spec(spec);
-            proc_spec_builder = specs::ProcSpecBuilder::new();
-          }
-          Err(specs::SpecError::MissingField(f)) => {
-            die(format!("proc spec missing field '{}'", f).as_ref())
-          }
-        }
-        arg_idx += 1;
+      Err(specs::SpecError::InvalidCredentials(err)) => return Err(format!("{}.", err)),
+      Err(specs::SpecError::InvalidEnvFile(err)) => return Err(format!("{}.", err)),
+      Err(specs::SpecError::InvalidCheck(err)) => return Err(format!("{}.", err)),
+      Err(specs::SpecError::InvalidNotificationFd(err)) => return Err(format!("{}.", err)),
+      Err(specs::SpecError::DuplicateName(name)) => {
+        return Err(format!("more than one process is named '{}'.", name))
       }
+    };
+  }
 
-      unknown => die(format!("unknown process spec argument: {}.", unknown).as_ref()),
+  match supervisor_spec_builder.build() {
+    Ok(spec) => Ok(spec),
+    Err(specs::SpecError::MissingField(f)) => Err(format!("supervisor spec missing field '{}'", f)),
+    Err(specs::SpecError::InvalidCredentials(err)) => Err(format!("{}.", err)),
+    Err(specs::SpecError::InvalidEnvFile(err)) => Err(format!("{}.", err)),
+    Err(specs::SpecError::InvalidCheck(err)) => Err(format!("{}.", err)),
+    Err(specs::SpecError::InvalidNotificationFd(err)) => Err(format!("{}.", err)),
+    Err(specs::SpecError::DuplicateName(name)) => {
+      Err(format!("more than one process is named '{}'.", name))
     }
   }
+}
+
+fn main() {
+  simple_logger::init().unwrap();
 
-  match proc_spec_builder.build() {
-    Ok(spec) => supervisor_spec_builder.add_proc_spec(spec),
-    Err(specs::SpecError::MissingField(f)) => {
-      die(format!("proc spec missing field '{}'", f).as_ref())
+  let args: Vec<String> = std::env::args().collect();
+
+  for a in &args {
+    if a == "--" {
+      break;
     }
-  };
 
-  let spec = match supervisor_spec_builder.build() {
-    Ok(spec) => spec,
-    Err(specs::SpecError::MissingField(f)) => {
-      die(format!("supervisor spec missing field '{}'", f).as_ref())
+    if a == "-h" || a == "-help" || a == "--help" {
+      usage();
     }
-  };
+
+    if a == "-version" || a == "--version" {
+      version();
+    }
+  }
+
+  let spec = parse_spec(&args).unwrap_or_else(|e| die(&e));
 
   let (sigtx, sigrx) = crossbeam_channel::bounded::<Signal>(64);
+  // Child exits only need to wake the supervisor, so they are coalesced
+  // into a single pending event that can never block the signal thread.
+  let (childtx, childrx) = crossbeam_channel::bounded::<()>(1);
 
+  if let Some(ref path) = spec.control_socket {
+    if let Err(err) = control::listen(path, sigtx.clone()) {
+      die(format!("unable to listen on control socket {}: {}.", path, err).as_ref());
+    }
+  }
+
+  // The kernel gives pid 1 no default signal actions, and container
+  // runtimes stop containers with SIGTERM, so as pid 1 SIGTERM means a
+  // graceful shutdown and SIGQUIT is used to terminate.
+  let pid1 = std::process::id() == 1;
+
+  // Signals are registered before any child is spawned so no exit is missed.
+  let signals = signal_hook::iterator::Signals::new([
+    signal_hook::SIGINT,
+    signal_hook::SIGTERM,
+    signal_hook::SIGQUIT,
+    signal_hook::SIGCHLD,
+    signal_hook::SIGHUP,
+    signal_hook::SIGUSR1,
+  ])
+  .unwrap_or_else(|e| die(format!("unable to register signal handlers: {}.", e).as_ref()));
+
+  let supervisor_sigtx = sigtx.clone();
   let _ = std::thread::spawn(move || {
-    if let Ok(signals) =
-      signal_hook::iterator::Signals::new(&[signal_hook::SIGINT, signal_hook::SIGTERM])
-    {
-      for signal in signals.forever() {
-        match signal {
-          signal_hook::SIGINT => {
-            let _ = sigtx.send(Signal::Shutdown);
-          }
-          signal_hook::SIGTERM => {
-            let _ = sigtx.send(Signal::Terminate);
-          }
-          _ => (),
+    for signal in signals.forever() {
+      match signal {
+        signal_hook::SIGINT => {
+          let _ = sigtx.send(Signal::Shutdown);
+        }
+        signal_hook::SIGTERM if pid1 => {
+          let _ = sigtx.send(Signal::Shutdown);
         }
+        signal_hook::SIGTERM | signal_hook::SIGQUIT => {
+          let _ = sigtx.send(Signal::Terminate(Some(signal)));
+        }
+        signal_hook::SIGCHLD => {
+          let _ = childtx.try_send(());
+        }
+        signal_hook::SIGHUP => {
+          let _ = sigtx.send(Signal::Reload(None));
+        }
+        signal_hook::SIGUSR1 => {
+          let _ = sigtx.send(Signal::Dump);
+        }
+        _ => (),
       }
     }
   });
 
-  if std::process::id() == 1 {
-    die(format!("running as pid 1 is not supported.").as_ref());
-  }
-
-  let mut supervisor = Supervisor::new(spec, sigrx);
+  let mut supervisor = Supervisor::new(spec, supervisor_sigtx, sigrx, childrx)
+    .unwrap_or_else(|e| die(format!("{}.", e).as_ref()));
   supervisor.supervise_forever();
 }
@@ -0,0 +1,291 @@
+use crate::Signal;
+use std::io::BufRead;
+use std::os::fd::AsRawFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+// A NOTIFY_SOCKET for a proc, speaking the sd_notify protocol. Each
+// datagram is newline separated KEY=VALUE pairs, which are forwarded to
+// the supervisor as a Signal::Notify.
+//
+// The socket is in the abstract namespace, so a run command that drops
+// privileges can still send to it, and nothing is left on disk. As any
+// local process can send to it, the reader thread drops messages from
+// processes that are not part of the run command. This is checked as
+// each message arrives, while a short lived sender like systemd-notify
+// still exists, and before anything is queued for the supervisor.
+pub struct Socket {
+  address: String,
+  socket: UnixDatagram,
+  closed: Arc<AtomicBool>,
+  run: Arc<(Mutex<Run>, Condvar)>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RunPid {
+  // The run command is being spawned, its pid is not known yet.
+  Spawning,
+  Running(libc::pid_t),
+  Stopped,
+}
+
+// The start of the proc and its run command, shared with the reader.
+struct Run {
+  generation: u64,
+  pid: RunPid,
+}
+
+// A notification from a proc. The generation is the start of the proc
+// it was received during, so messages still queued from before a
+// restart can be told apart.
+pub struct Message {
+  pub service: String,
+  pub generation: u64,
+  pub vars: Vec<(String, String)>,
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl Socket {
+  pub fn bind(
+    service: &str,
+    cgroup: Option<std::path::PathBuf>,
+    sigtx: crossbeam_channel::Sender<Signal>,
+  ) -> Result<Socket, std::io::Error> {
+    let address = format!(
+      "orderly/{}/{}",
+      std::process::id(),
+      NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    );
+    let socket = UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(address.as_bytes())?)?;
+    let on: libc::c_int = 1;
+    if unsafe {
+      libc::setsockopt(
+        socket.as_raw_fd(),
+        libc::SOL_SOCKET,
+        libc::SO_PASSCRED,
+        &on as *const libc::c_int as *const libc::c_void,
+        std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+      )
+    } != 0
+    {
+      return Err(std::io::Error::last_os_error());
+    }
+    let reader = socket.try_clone()?;
+    let closed = Arc::new(AtomicBool::new(false));
+    let run = Arc::new((
+      Mutex::new(Run {
+        generation: 0,
+        pid: RunPid::Stopped,
+      }),
+      Condvar::new(),
+    ));
+
+    let service = service.to_string();
+    let reader_closed = closed.clone();
+    let reader_run = run.clone();
+    std::thread::spawn(move || {
+      let mut buf = vec![0u8; 4096];
+      loop {
+        let (n, pid) = match recv_with_pid(&reader, &mut buf) {
+          Ok(r) => r,
+          Err(err) => {
+            log::warn!("unable to read notify socket of {}: {}.", service, err);
+            return;
+          }
+        };
+        if reader_closed.load(Ordering::SeqCst) {
+          return;
+        }
+        let vars = parse(&buf[..n]);
+        if vars.is_empty() {
+          continue;
+        }
+        // A message can arrive before spawn returns the run command's pid.
+        let (generation, run_pid) = {
+          let (lock, spawned) = &*reader_run;
+          let run = spawned
+            .wait_while(lock.lock().unwrap(), |r| r.pid == RunPid::Spawning)
+            .unwrap();
+          (run.generation, run.pid)
+        };
+        if !is_run_process(run_pid, pid, cgroup.as_deref()) {
+          log::warn!(
+            "ignoring notify message for {} from pid {}, which is not part of the run command.",
+            service,
+            pid
+          );
+          continue;
+        }
+        let msg = Message {
+          service: service.clone(),
+          generation,
+          vars,
+        };
+        // Never block on a busy supervisor, that would also hold up
+        // signals queued behind these messages.
+        match sigtx.try_send(Signal::Notify(msg)) {
+          Ok(()) => (),
+          Err(crossbeam_channel::TrySendError::Full(_)) => {
+            log::warn!(
+              "dropping notify message for {}, the supervisor is busy.",
+              service
+            );
+          }
+          Err(crossbeam_channel::TrySendError::Disconnected(_)) => return,
+        }
+      }
+    });
+
+    Ok(Socket {
+      address,
+      socket,
+      closed,
+      run,
+    })
+  }
+
+  // Called just before the run command is spawned, later messages are
+  // tagged with the new generation, and wait for spawned to be called.
+  pub fn spawning(&self, generation: u64) {
+    let mut run = self.run.0.lock().unwrap();
+    run.generation = generation;
+    run.pid = RunPid::Spawning;
+  }
+
+  // The pid of the run command, or None if it failed to spawn.
+  pub fn spawned(&self, pid: Option<u32>) {
+    let (lock, spawned) = &*self.run;
+    lock.lock().unwrap().pid = match pid {
+      Some(pid) => RunPid::Running(pid as libc::pid_t),
+      None => RunPid::Stopped,
+    };
+    spawned.notify_all();
+  }
+
+  // The value of NOTIFY_SOCKET, '@' marks an abstract address.
+  pub fn env(&self) -> (String, String) {
+    (String::from("NOTIFY_SOCKET"), format!("@{}", self.address))
+  }
+}
+
+impl Drop for Socket {
+  // Shutting the socket down wakes the reader thread so it can exit.
+  fn drop(&mut self) {
+    self.closed.store(true, Ordering::SeqCst);
+    let _ = self.socket.shutdown(std::net::Shutdown::Both);
+  }
+}
+
+// The s6 readiness protocol, the run command writes a newline to the
+// write end of a pipe when it is ready. This is forwarded as READY=1.
+pub fn watch_notification_fd(
+  service: &str,
+  generation: u64,
+  pipe: std::fs::File,
+  sigtx: crossbeam_channel::Sender<Signal>,
+) {
+  let service = service.to_string();
+  std::thread::spawn(move || {
+    let mut line = vec![];
+    // EOF without a newline means the run command exited or closed the
+    // fd without becoming ready.
+    if let Ok(n) = std::io::BufReader::new(pipe).read_until(b'\n', &mut line) {
+      if n > 0 && line.ends_with(b"\n") {
+        let _ = sigtx.send(Signal::Notify(Message {
+          service,
+          generation,
+          vars: vec![(String::from("READY"), String::from("1"))],
+        }));
+      }
+    }
+  });
+}
+
+// Like systemd's NotifyAccess=main, but also accepting the run
+// command's process group and cgroup so a wrapper script can notify
+// on behalf of the service it starts.
+fn is_run_process(run_pid: RunPid, pid: libc::pid_t, cgroup: Option<&std::path::Path>) -> bool {
+  let run_pid = match run_pid {
+    RunPid::Running(run_pid) => run_pid,
+    _ => return false,
+  };
+  if pid == run_pid || unsafe { libc::getpgid(pid) } == run_pid {
+    return true;
+  }
+  match cgroup {
+    Some(cgroup) => crate::cgroup::pids(cgroup)
+      .map(|p| p.contains(&pid))
+      .unwrap_or(false),
+    None => false,
+  }
+}
+
+// Receives a datagram along with the pid the kernel attached to it.
+// Any fds passed along with it are closed, nothing is expecting them.
+fn recv_with_pid(
+  socket: &UnixDatagram,
+  buf: &mut [u8],
+) -> Result<(usize, libc::pid_t), std::io::Error> {
+  loop {
+    let mut iov = libc::iovec {
+      iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+      iov_len: buf.len(),
+    };
+    let mut control = [0u64; 32];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+    let n = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) };
+    if n < 0 {
+      let err = std::io::Error::last_os_error();
+      if err.kind() == std::io::ErrorKind::Interrupted {
+        continue;
+      }
+      return Err(err);
+    }
+
+    let mut pid = None;
+    unsafe {
+      let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+      while !cmsg.is_null() {
+        let data = libc::CMSG_DATA(cmsg);
+        let len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+        match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+          (libc::SOL_SOCKET, libc::SCM_CREDENTIALS) => {
+            let cred = std::ptr::read_unaligned(data as *const libc::ucred);
+            pid = Some(cred.pid);
+          }
+          (libc::SOL_SOCKET, libc::SCM_RIGHTS) => {
+            for i in 0..len / std::mem::size_of::<libc::c_int>() {
+              libc::close(std::ptr::read_unaligned(
+                (data as *const libc::c_int).add(i),
+              ));
+            }
+          }
+          _ => (),
+        }
+        cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+      }
+    }
+
+    // With SO_PASSCRED the kernel always attaches credentials, a
+    // message without them is dropped rather than trusted.
+    if let Some(pid) = pid {
+      return Ok((n as usize, pid));
+    }
+  }
+}
+
+fn parse(datagram: &[u8]) -> Vec<(String, String)> {
+  String::from_utf8_lossy(datagram)
+    .lines()
+    .filter_map(|l| l.split_once('='))
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect()
+}
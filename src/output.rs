@@ -0,0 +1,130 @@
+use crate::logs;
+use crate::specs;
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Condvar, Mutex};
+
+const COLORS: [u8; 6] = [36, 33, 32, 35, 34, 31];
+
+// A Prefixer gives each spawned child its own pipe, every line read
+// from the pipe is written to our stdout as 'NAME.ACTION | LINE' so the
+// output of many processes can be told apart.
+pub struct Prefixer {
+  timestamps: bool,
+  color: bool,
+  width: usize,
+  readers: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl Prefixer {
+  pub fn new(spec: &specs::SupervisorSpec) -> Option<Self> {
+    let timestamps = match spec.output {
+      specs::OutputMode::Inherit => return None,
+      specs::OutputMode::Prefixed => false,
+      specs::OutputMode::Timestamped => true,
+    };
+
+    let color = match spec.output_color {
+      specs::ColorMode::Auto => unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 },
+      specs::ColorMode::Always => true,
+      specs::ColorMode::Never => false,
+    };
+
+    let longest_name = spec
+      .procs
+      .iter()
+      .map(|p| p.name.len())
+      .chain(std::iter::once("orderly".len()))
+      .max()
+      .unwrap_or(0);
+
+    Some(Prefixer {
+      timestamps,
+      color,
+      width: longest_name + ".start_complete".len(),
+      readers: Arc::new((Mutex::new(0), Condvar::new())),
+    })
+  }
+
+  pub fn stdio(
+    &self,
+    name: &str,
+    action: &str,
+  ) -> Result<(std::process::Stdio, std::process::Stdio), std::io::Error> {
+    let (read_end, write_end) = logs::pipe()?;
+
+    let label = format!(
+      "{:width$}",
+      format!("{}.{}", name, action.to_lowercase()),
+      width = self.width
+    );
+    let prefix = if self.color {
+      // Colors are picked by name so a process keeps its color across restarts.
+      let hash = name
+        .bytes()
+        .fold(0usize, |h, b| h.wrapping_mul(31).wrapping_add(b as usize));
+      format!("\x1b[{}m{} |\x1b[0m ", COLORS[hash % COLORS.len()], label)
+    } else {
+      format!("{} | ", label)
+    };
+    let timestamps = self.timestamps;
+    let readers = self.readers.clone();
+    *readers.0.lock().unwrap() += 1;
+
+    std::thread::spawn(move || {
+      let mut reader = std::io::BufReader::new(read_end);
+      let mut line = vec![];
+      loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+          Ok(0) => break,
+          Ok(_) => {
+            if line.last() != Some(&b'\n') {
+              line.push(b'\n');
+            }
+            let mut out = vec![];
+            if timestamps {
+              out.extend_from_slice(timestamp().as_bytes());
+            }
+            out.extend_from_slice(prefix.as_bytes());
+            out.extend_from_slice(&line);
+            // A single write per line keeps lines from different processes whole.
+            let _ = std::io::stdout().lock().write_all(&out);
+          }
+          Err(ref err) if err.kind() == std::io::ErrorKind::Interrupted => (),
+          Err(_) => break,
+        }
+      }
+      *readers.0.lock().unwrap() -= 1;
+      readers.1.notify_all();
+    });
+
+    Ok((
+      std::process::Stdio::from(write_end.try_clone()?),
+      std::process::Stdio::from(write_end),
+    ))
+  }
+
+  // Wait for output from exited children to be written before we exit,
+  // orphaned grandchildren may hold a pipe open forever so give up at
+  // the timeout.
+  pub fn flush(&self, timeout: std::time::Duration) {
+    let (count, cond) = &*self.readers;
+    let _ = cond.wait_timeout_while(count.lock().unwrap(), timeout, |n| *n > 0);
+  }
+}
+
+fn timestamp() -> String {
+  let now = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .unwrap_or_default();
+  let t = now.as_secs() as libc::time_t;
+  let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+  unsafe { libc::localtime_r(&t, &mut tm) };
+  format!(
+    "{:02}:{:02}:{:02}.{:03} ",
+    tm.tm_hour,
+    tm.tm_min,
+    tm.tm_sec,
+    now.subsec_millis()
+  )
+}
@@ -0,0 +1,68 @@
+// On Linux orderly is a child subreaper, so processes that escape their
+// process group and are orphaned (for example by double forking) are
+// reparented to orderly instead of init. They are attributed to a
+// process by the ORDERLY_SERVICE_NAME they inherited in their env.
+
+#[cfg(target_os = "linux")]
+pub fn set_child_subreaper() -> Result<bool, std::io::Error> {
+  if unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) } != 0 {
+    return Err(std::io::Error::last_os_error());
+  }
+  Ok(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_child_subreaper() -> Result<bool, std::io::Error> {
+  Ok(false)
+}
+
+// All live children of this process.
+#[cfg(target_os = "linux")]
+pub fn children() -> Vec<i32> {
+  let self_pid = std::process::id() as i32;
+  let mut pids = vec![];
+  let entries = match std::fs::read_dir("/proc") {
+    Ok(entries) => entries,
+    Err(_) => return pids,
+  };
+  for entry in entries.flatten() {
+    let pid = match entry
+      .file_name()
+      .to_str()
+      .and_then(|s| s.parse::<i32>().ok())
+    {
+      Some(pid) => pid,
+      None => continue,
+    };
+    let stat = match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+      Ok(stat) => stat,
+      Err(_) => continue,
+    };
+    // The command name may contain spaces, fields are counted from the last ')'.
+    let fields: Vec<&str> = match stat.rfind(')') {
+      Some(i) => stat[i + 1..].split_whitespace().collect(),
+      None => continue,
+    };
+    let (state, ppid) = match (fields.first(), fields.get(1)) {
+      (Some(state), Some(ppid)) => (*state, ppid.parse::<i32>().unwrap_or(0)),
+      _ => continue,
+    };
+    if ppid == self_pid && state != "Z" {
+      pids.push(pid);
+    }
+  }
+  pids
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn children() -> Vec<i32> {
+  vec![]
+}
+
+pub fn service_name(pid: i32) -> Option<String> {
+  let environ = std::fs::read(format!("/proc/{}/environ", pid)).ok()?;
+  environ
+    .split(|b| *b == 0)
+    .find_map(|v| v.strip_prefix(b"ORDERLY_SERVICE_NAME="))
+    .map(|name| String::from_utf8_lossy(name).into_owned())
+}
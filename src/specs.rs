@@ -1,21 +1,304 @@
+use crate::checks;
+use crate::envfile;
+use crate::users;
+
+// A command is an argument vector, the first element is the program
+// which is looked up in PATH if it contains no slash.
+pub type Command = Vec<String>;
+
 #[derive(Debug)]
 pub enum SpecError {
   MissingField(&'static str),
+  InvalidCredentials(String),
+  InvalidEnvFile(String),
+  InvalidCheck(String),
+  InvalidNotificationFd(String),
+  DuplicateName(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartStrategy {
+  OneForAll,
+  OneForOne,
+  RestForOne,
+}
+
+impl RestartStrategy {
+  pub fn parse(s: &str) -> Option<RestartStrategy> {
+    match s {
+      "one_for_all" => Some(RestartStrategy::OneForAll),
+      "one_for_one" => Some(RestartStrategy::OneForOne),
+      "rest_for_one" => Some(RestartStrategy::RestForOne),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusFormat {
+  Text,
+  Json,
+}
+
+impl StatusFormat {
+  pub fn parse(s: &str) -> Option<StatusFormat> {
+    match s {
+      "text" => Some(StatusFormat::Text),
+      "json" => Some(StatusFormat::Json),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+  Inherit,
+  Prefixed,
+  Timestamped,
+}
+
+impl OutputMode {
+  pub fn parse(s: &str) -> Option<OutputMode> {
+    match s {
+      "inherit" => Some(OutputMode::Inherit),
+      "prefixed" => Some(OutputMode::Prefixed),
+      "timestamped" => Some(OutputMode::Timestamped),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMode {
+  Auto,
+  Always,
+  Never,
+}
+
+impl ColorMode {
+  pub fn parse(s: &str) -> Option<ColorMode> {
+    match s {
+      "auto" => Some(ColorMode::Auto),
+      "always" => Some(ColorMode::Always),
+      "never" => Some(ColorMode::Never),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IoPrio {
+  RealTime(u8),
+  BestEffort(u8),
+  Idle,
+}
+
+impl IoPrio {
+  // Either 'idle', or 'realtime:LEVEL' or 'best_effort:LEVEL' with a level
+  // from 0 (highest) to 7 (lowest), the level defaults to 4.
+  pub fn parse(s: &str) -> Option<IoPrio> {
+    let mut parts = s.splitn(2, ':');
+    let class = parts.next()?;
+    let level = match parts.next() {
+      Some(level) => level.parse::<u8>().ok().filter(|l| *l <= 7)?,
+      None => 4,
+    };
+    match class {
+      "realtime" => Some(IoPrio::RealTime(level)),
+      "best_effort" => Some(IoPrio::BestEffort(level)),
+      "idle" => Some(IoPrio::Idle),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenAddress {
+  Tcp(String),
+  Unix(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenSpec {
+  pub name: Option<String>,
+  pub address: ListenAddress,
+}
+
+impl ListenSpec {
+  // '[NAME=]tcp:HOST:PORT' or '[NAME=]unix:PATH', the name is passed in
+  // LISTEN_FDNAMES.
+  pub fn parse(s: &str) -> Option<ListenSpec> {
+    let (name, address) = match s.split_once('=') {
+      Some((name, address)) if !name.is_empty() && !name.contains(':') => {
+        (Some(name.to_string()), address)
+      }
+      _ => (None, s),
+    };
+    let address = if let Some(address) = address.strip_prefix("tcp:") {
+      address.rsplit_once(':')?.1.parse::<u16>().ok()?;
+      ListenAddress::Tcp(address.to_string())
+    } else if let Some(path) = address.strip_prefix("unix:") {
+      if path.is_empty() {
+        return None;
+      }
+      ListenAddress::Unix(path.to_string())
+    } else {
+      return None;
+    };
+    Some(ListenSpec { name, address })
+  }
+}
+
+// A resource limit, either a number or 'unlimited'.
+pub fn parse_rlimit(s: &str) -> Option<u64> {
+  match s {
+    // Truncates to RLIM_INFINITY when converted to rlim_t.
+    "unlimited" => Some(u64::MAX),
+    s => s.parse::<u64>().ok(),
+  }
+}
+
+// A cpu list such as '0-3,6'.
+pub fn parse_cpu_list(s: &str) -> Option<Vec<usize>> {
+  let mut cpus = vec![];
+  for part in s.split(',') {
+    let mut range = part.splitn(2, '-');
+    let start = range.next()?.trim().parse::<usize>().ok()?;
+    let end = match range.next() {
+      Some(end) => end.trim().parse::<usize>().ok()?,
+      None => start,
+    };
+    if end < start || end >= libc::CPU_SETSIZE as usize {
+      return None;
+    }
+    cpus.extend(start..=end);
+  }
+  Some(cpus)
+}
+
+// A health check run inside orderly rather than as a check command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuiltinCheck {
+  Tcp(String),
+  Http {
+    url: String,
+    status: u64,
+  },
+  Unix(String),
+  File {
+    path: String,
+    max_age_seconds: Option<f64>,
+  },
+}
+
+// The working directory and environment of commands. The supervisor's
+// is the default for every process, a process's own settings are
+// applied on top.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnvSpec {
+  pub dir: Option<String>,
+  pub clear_env: bool,
+  pub pass_env: Vec<String>,
+  pub env: Vec<(String, String)>,
+}
+
+#[derive(Debug, Default)]
+pub struct EnvSpecBuilder {
+  dir: Option<String>,
+  clear_env: bool,
+  pass_env: Vec<String>,
+  env_files: Vec<String>,
+  env: Vec<(String, String)>,
+}
+
+impl EnvSpecBuilder {
+  pub fn set_dir(&mut self, dir: String) {
+    self.dir = Some(dir)
+  }
+
+  pub fn set_clear_env(&mut self, clear_env: bool) {
+    self.clear_env = clear_env
+  }
+
+  // Passing a variable implies clearing all the others.
+  pub fn add_pass_env(&mut self, name: String) {
+    self.clear_env = true;
+    self.pass_env.push(name)
+  }
+
+  pub fn add_env_file(&mut self, path: String) {
+    self.env_files.push(path)
+  }
+
+  pub fn add_env(&mut self, key: String, value: String) {
+    self.env.push((key, value))
+  }
+
+  // Env files are read once at startup, variables set directly
+  // override those from env files.
+  pub fn build(self) -> Result<EnvSpec, SpecError> {
+    let mut env = vec![];
+    for path in self.env_files.iter() {
+      env.extend(envfile::load(path).map_err(SpecError::InvalidEnvFile)?);
+    }
+    env.extend(self.env);
+    Ok(EnvSpec {
+      dir: self.dir,
+      clear_env: self.clear_env,
+      pass_env: self.pass_env,
+      env,
+    })
+  }
 }
 
 #[derive(Debug)]
 pub struct ProcSpecBuilder {
   name: Option<String>,
-  run: Option<String>,
-  wait_started: Option<String>,
+  run: Option<Command>,
+  wait_started: Option<Command>,
   wait_started_timeout_seconds: Option<f64>,
-  check: Option<String>,
+  notify: bool,
+  watchdog_seconds: Option<f64>,
+  notification_fd: Option<i32>,
+  listen: Vec<ListenSpec>,
+  check: Option<Command>,
   check_timeout_seconds: Option<f64>,
-  shutdown: Option<String>,
+  check_tcp: Option<String>,
+  check_http: Option<String>,
+  check_http_status: u64,
+  check_unix: Option<String>,
+  check_file: Option<String>,
+  check_file_max_age_seconds: Option<f64>,
+  shutdown: Option<Command>,
   shutdown_timeout_seconds: Option<f64>,
   terminate_timeout_seconds: Option<f64>,
-  cleanup: Option<String>,
+  cleanup: Option<Command>,
   cleanup_timeout_seconds: Option<f64>,
+  log_file: Option<String>,
+  log_dir: Option<String>,
+  log_max_bytes: u64,
+  log_keep: u64,
+  memory_max: Option<u64>,
+  cpu_max: Option<f64>,
+  pids_max: Option<u64>,
+  rlimit_nofile: Option<u64>,
+  rlimit_core: Option<u64>,
+  rlimit_as: Option<u64>,
+  rlimit_nproc: Option<u64>,
+  nice: Option<i32>,
+  ioprio: Option<IoPrio>,
+  oom_score_adj: Option<i32>,
+  cpu_affinity: Option<Vec<usize>>,
+  user: Option<String>,
+  group: Option<String>,
+  groups: Option<Vec<String>>,
+  hook_user: Option<String>,
+  hook_group: Option<String>,
+  environment: EnvSpecBuilder,
 }
 
 fn set_optional_timeout(v: &mut Option<f64>, timeout_seconds: f64) {
@@ -33,40 +316,199 @@ impl ProcSpecBuilder {
       run: None,
       check: None,
       check_timeout_seconds: Some(120.0),
+      check_tcp: None,
+      check_http: None,
+      check_http_status: 200,
+      check_unix: None,
+      check_file: None,
+      check_file_max_age_seconds: None,
       wait_started: None,
       wait_started_timeout_seconds: Some(120.0),
+      notify: false,
+      watchdog_seconds: None,
+      notification_fd: None,
+      listen: vec![],
       shutdown: None,
       shutdown_timeout_seconds: Some(120.0),
       cleanup: None,
       cleanup_timeout_seconds: Some(120.0),
       terminate_timeout_seconds: Some(10.0),
+      log_file: None,
+      log_dir: None,
+      log_max_bytes: 10 * 1024 * 1024,
+      log_keep: 5,
+      memory_max: None,
+      cpu_max: None,
+      pids_max: None,
+      rlimit_nofile: None,
+      rlimit_core: None,
+      rlimit_as: None,
+      rlimit_nproc: None,
+      nice: None,
+      ioprio: None,
+      oom_score_adj: None,
+      cpu_affinity: None,
+      user: None,
+      group: None,
+      groups: None,
+      hook_user: None,
+      hook_group: None,
+      environment: EnvSpecBuilder::default(),
     }
   }
 
+  pub fn name(&self) -> Option<&str> {
+    self.name.as_deref()
+  }
+
   pub fn set_name(&mut self, name: String) {
     self.name = Some(name)
   }
 
-  pub fn set_run(&mut self, run: String) {
+  pub fn set_run(&mut self, run: Command) {
     self.run = Some(run)
   }
 
-  pub fn set_check(&mut self, check: String) {
+  pub fn set_check(&mut self, check: Command) {
     self.check = Some(check)
   }
 
-  pub fn set_wait_started(&mut self, wait_started: String) {
+  pub fn set_check_tcp(&mut self, address: String) {
+    self.check_tcp = Some(address)
+  }
+
+  pub fn set_check_http(&mut self, url: String) {
+    self.check_http = Some(url)
+  }
+
+  pub fn set_check_http_status(&mut self, status: u64) {
+    self.check_http_status = status
+  }
+
+  pub fn set_check_unix(&mut self, path: String) {
+    self.check_unix = Some(path)
+  }
+
+  pub fn set_check_file(&mut self, path: String) {
+    self.check_file = Some(path)
+  }
+
+  pub fn set_check_file_max_age_seconds(&mut self, max_age_seconds: f64) {
+    self.check_file_max_age_seconds = Some(max_age_seconds)
+  }
+
+  pub fn set_wait_started(&mut self, wait_started: Command) {
     self.wait_started = Some(wait_started)
   }
 
-  pub fn set_cleanup(&mut self, cleanup: String) {
+  pub fn set_notify(&mut self, notify: bool) {
+    self.notify = notify
+  }
+
+  pub fn set_watchdog_seconds(&mut self, watchdog_seconds: f64) {
+    set_optional_timeout(&mut self.watchdog_seconds, watchdog_seconds)
+  }
+
+  pub fn set_notification_fd(&mut self, fd: i32) {
+    self.notification_fd = Some(fd)
+  }
+
+  pub fn add_listen(&mut self, listen: ListenSpec) {
+    self.listen.push(listen)
+  }
+
+  pub fn set_cleanup(&mut self, cleanup: Command) {
     self.cleanup = Some(cleanup)
   }
 
-  pub fn set_shutdown(&mut self, shutdown: String) {
+  pub fn set_shutdown(&mut self, shutdown: Command) {
     self.shutdown = Some(shutdown)
   }
 
+  pub fn set_log_file(&mut self, log_file: String) {
+    self.log_file = Some(log_file)
+  }
+
+  pub fn set_log_dir(&mut self, log_dir: String) {
+    self.log_dir = Some(log_dir)
+  }
+
+  pub fn set_log_max_bytes(&mut self, log_max_bytes: u64) {
+    self.log_max_bytes = log_max_bytes
+  }
+
+  pub fn set_log_keep(&mut self, log_keep: u64) {
+    self.log_keep = log_keep
+  }
+
+  pub fn set_memory_max(&mut self, memory_max: u64) {
+    self.memory_max = Some(memory_max)
+  }
+
+  pub fn set_cpu_max(&mut self, cpu_max: f64) {
+    self.cpu_max = Some(cpu_max)
+  }
+
+  pub fn set_pids_max(&mut self, pids_max: u64) {
+    self.pids_max = Some(pids_max)
+  }
+
+  pub fn set_rlimit_nofile(&mut self, limit: u64) {
+    self.rlimit_nofile = Some(limit)
+  }
+
+  pub fn set_rlimit_core(&mut self, limit: u64) {
+    self.rlimit_core = Some(limit)
+  }
+
+  pub fn set_rlimit_as(&mut self, limit: u64) {
+    self.rlimit_as = Some(limit)
+  }
+
+  pub fn set_rlimit_nproc(&mut self, limit: u64) {
+    self.rlimit_nproc = Some(limit)
+  }
+
+  pub fn set_nice(&mut self, nice: i32) {
+    self.nice = Some(nice)
+  }
+
+  pub fn set_ioprio(&mut self, ioprio: IoPrio) {
+    self.ioprio = Some(ioprio)
+  }
+
+  pub fn set_oom_score_adj(&mut self, oom_score_adj: i32) {
+    self.oom_score_adj = Some(oom_score_adj)
+  }
+
+  pub fn set_cpu_affinity(&mut self, cpus: Vec<usize>) {
+    self.cpu_affinity = Some(cpus)
+  }
+
+  pub fn set_user(&mut self, user: String) {
+    self.user = Some(user)
+  }
+
+  pub fn set_group(&mut self, group: String) {
+    self.group = Some(group)
+  }
+
+  pub fn set_groups(&mut self, groups: Vec<String>) {
+    self.groups = Some(groups)
+  }
+
+  pub fn set_hook_user(&mut self, user: String) {
+    self.hook_user = Some(user)
+  }
+
+  pub fn set_hook_group(&mut self, group: String) {
+    self.hook_group = Some(group)
+  }
+
+  pub fn environment(&mut self) -> &mut EnvSpecBuilder {
+    &mut self.environment
+  }
+
   pub fn set_wait_started_timeout_seconds(&mut self, timeout_seconds: f64) {
     set_optional_timeout(&mut self.wait_started_timeout_seconds, timeout_seconds)
   }
@@ -90,9 +532,10 @@ impl ProcSpecBuilder {
   pub fn build(self) -> Result<ProcSpec, SpecError> {
     let mut spec = ProcSpec {
       name: "".to_string(),
-      run: "".to_string(),
+      run: vec![],
       check: self.check,
       check_timeout_seconds: self.check_timeout_seconds,
+      builtin_checks: vec![],
       shutdown: self.shutdown,
       shutdown_timeout_seconds: self.shutdown_timeout_seconds,
       terminate_timeout_seconds: self.terminate_timeout_seconds,
@@ -100,63 +543,196 @@ impl ProcSpecBuilder {
       cleanup_timeout_seconds: self.cleanup_timeout_seconds,
       wait_started: self.wait_started,
       wait_started_timeout_seconds: self.wait_started_timeout_seconds,
+      notify: self.notify,
+      watchdog_seconds: self.watchdog_seconds,
+      notification_fd: self.notification_fd,
+      listen: self.listen,
+      log_file: self.log_file,
+      log_max_bytes: self.log_max_bytes,
+      log_keep: self.log_keep,
+      memory_max: self.memory_max,
+      cpu_max: self.cpu_max,
+      pids_max: self.pids_max,
+      rlimit_nofile: self.rlimit_nofile,
+      rlimit_core: self.rlimit_core,
+      rlimit_as: self.rlimit_as,
+      rlimit_nproc: self.rlimit_nproc,
+      nice: self.nice,
+      ioprio: self.ioprio,
+      oom_score_adj: self.oom_score_adj,
+      cpu_affinity: self.cpu_affinity,
+      run_credentials: None,
+      hook_credentials: None,
+      environment: self.environment.build()?,
     };
     match &self.name {
       Some(name) => spec.name = name.clone(),
       None => return Err(SpecError::MissingField("name")),
     }
 
+    if spec.log_file.is_none() {
+      if let Some(ref log_dir) = self.log_dir {
+        let path = std::path::Path::new(log_dir).join(format!("{}.log", spec.name));
+        spec.log_file = Some(path.to_string_lossy().into_owned());
+      }
+    }
+
     match &self.run {
       Some(run) => spec.run = run.clone(),
       None => return Err(SpecError::MissingField("run")),
     }
 
+    if let Some(address) = self.check_tcp {
+      spec.builtin_checks.push(BuiltinCheck::Tcp(address));
+    }
+    if let Some(url) = self.check_http {
+      checks::parse_http_url(&url).map_err(SpecError::InvalidCheck)?;
+      spec.builtin_checks.push(BuiltinCheck::Http {
+        url,
+        status: self.check_http_status,
+      });
+    }
+    if let Some(path) = self.check_unix {
+      spec.builtin_checks.push(BuiltinCheck::Unix(path));
+    }
+    if let Some(path) = self.check_file {
+      spec.builtin_checks.push(BuiltinCheck::File {
+        path,
+        max_age_seconds: self.check_file_max_age_seconds,
+      });
+    }
+
+    // Stdin, stdout and stderr are already taken by the process.
+    if let Some(fd) = spec.notification_fd {
+      if fd < 3 {
+        return Err(SpecError::InvalidNotificationFd(format!(
+          "notification fd {} must be 3 or greater",
+          fd
+        )));
+      }
+      // Listen sockets are passed on fds 3 and up.
+      if (fd as usize) < 3 + spec.listen.len() {
+        return Err(SpecError::InvalidNotificationFd(format!(
+          "notification fd {} overlaps the listen fds 3 to {}",
+          fd,
+          2 + spec.listen.len()
+        )));
+      }
+    }
+
+    spec.run_credentials = users::resolve(
+      self.user.as_deref(),
+      self.group.as_deref(),
+      self.groups.as_deref(),
+    )
+    .map_err(SpecError::InvalidCredentials)?;
+
+    // Hooks run with the same credentials as the run command unless
+    // they are given their own. A hook group on its own only replaces
+    // the run command's primary group.
+    spec.hook_credentials = match (&self.hook_user, &self.hook_group) {
+      (Some(user), group) => {
+        users::resolve(Some(user), group.as_deref(), None).map_err(SpecError::InvalidCredentials)?
+      }
+      (None, Some(group)) => Some(
+        users::with_group(spec.run_credentials.as_ref(), group)
+          .map_err(SpecError::InvalidCredentials)?,
+      ),
+      (None, None) => spec.run_credentials.clone(),
+    };
+
     Ok(spec)
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct ProcSpec {
   pub name: String,
-  pub run: String,
-  pub wait_started: Option<String>,
+  pub run: Command,
+  pub wait_started: Option<Command>,
   pub wait_started_timeout_seconds: Option<f64>,
-  pub check: Option<String>,
+  pub notify: bool,
+  pub watchdog_seconds: Option<f64>,
+  pub notification_fd: Option<i32>,
+  pub listen: Vec<ListenSpec>,
+  pub check: Option<Command>,
   pub check_timeout_seconds: Option<f64>,
-  pub shutdown: Option<String>,
+  pub builtin_checks: Vec<BuiltinCheck>,
+  pub shutdown: Option<Command>,
   pub shutdown_timeout_seconds: Option<f64>,
   pub terminate_timeout_seconds: Option<f64>,
-  pub cleanup: Option<String>,
+  pub cleanup: Option<Command>,
   pub cleanup_timeout_seconds: Option<f64>,
+  pub log_file: Option<String>,
+  pub log_max_bytes: u64,
+  pub log_keep: u64,
+  pub memory_max: Option<u64>,
+  pub cpu_max: Option<f64>,
+  pub pids_max: Option<u64>,
+  pub rlimit_nofile: Option<u64>,
+  pub rlimit_core: Option<u64>,
+  pub rlimit_as: Option<u64>,
+  pub rlimit_nproc: Option<u64>,
+  pub nice: Option<i32>,
+  pub ioprio: Option<IoPrio>,
+  pub oom_score_adj: Option<i32>,
+  pub cpu_affinity: Option<Vec<usize>>,
+  pub run_credentials: Option<users::Credentials>,
+  pub hook_credentials: Option<users::Credentials>,
+  pub environment: EnvSpec,
 }
 
 #[derive(Debug)]
 pub struct SupervisorSpecBuilder {
   status_file: Option<String>,
+  status_format: StatusFormat,
+  control_socket: Option<String>,
+  output: OutputMode,
+  output_color: ColorMode,
+  cgroup_parent: Option<String>,
   pub restart_tokens_per_second: f64,
   pub max_restart_tokens: f64,
   pub check_delay_seconds: f64,
-  pub start_complete: Option<String>,
+  pub restart_strategy: RestartStrategy,
+  pub restart_backoff_seconds: f64,
+  pub restart_backoff_max_seconds: f64,
+  pub restart_backoff_multiplier: f64,
+  pub restart_backoff_jitter: f64,
+  pub restart_backoff_reset_seconds: f64,
+  pub start_complete: Option<Command>,
   pub start_complete_timeout: Option<f64>,
-  pub restart: Option<String>,
+  pub restart: Option<Command>,
   pub restart_timeout: Option<f64>,
-  pub failure: Option<String>,
+  pub failure: Option<Command>,
   pub failure_timeout: Option<f64>,
+  environment: EnvSpecBuilder,
   procs: Vec<ProcSpec>,
 }
 
 #[derive(Debug)]
 pub struct SupervisorSpec {
   pub status_file: Option<String>,
+  pub status_format: StatusFormat,
+  pub control_socket: Option<String>,
+  pub output: OutputMode,
+  pub output_color: ColorMode,
+  pub cgroup_parent: Option<String>,
   pub restart_tokens_per_second: f64,
   pub check_delay_seconds: f64,
   pub max_restart_tokens: f64,
-  pub start_complete: Option<String>,
+  pub restart_strategy: RestartStrategy,
+  pub restart_backoff_seconds: f64,
+  pub restart_backoff_max_seconds: f64,
+  pub restart_backoff_multiplier: f64,
+  pub restart_backoff_jitter: f64,
+  pub restart_backoff_reset_seconds: f64,
+  pub start_complete: Option<Command>,
   pub start_complete_timeout: Option<f64>,
-  pub restart: Option<String>,
+  pub restart: Option<Command>,
   pub restart_timeout: Option<f64>,
-  pub failure: Option<String>,
+  pub failure: Option<Command>,
   pub failure_timeout: Option<f64>,
+  pub environment: EnvSpec,
   pub procs: Vec<ProcSpec>,
 }
 
@@ -166,6 +742,12 @@ impl SupervisorSpecBuilder {
       restart_tokens_per_second: 0.1,
       max_restart_tokens: 5.0,
       check_delay_seconds: 5.0,
+      restart_strategy: RestartStrategy::OneForAll,
+      restart_backoff_seconds: 0.0,
+      restart_backoff_max_seconds: 60.0,
+      restart_backoff_multiplier: 2.0,
+      restart_backoff_jitter: 0.1,
+      restart_backoff_reset_seconds: 60.0,
       start_complete: None,
       start_complete_timeout: Some(120.0),
       restart: None,
@@ -173,6 +755,12 @@ impl SupervisorSpecBuilder {
       failure: None,
       failure_timeout: Some(120.0),
       status_file: None,
+      status_format: StatusFormat::Text,
+      control_socket: None,
+      output: OutputMode::Inherit,
+      output_color: ColorMode::Auto,
+      cgroup_parent: None,
+      environment: EnvSpecBuilder::default(),
       procs: vec![],
     }
   }
@@ -189,11 +777,55 @@ impl SupervisorSpecBuilder {
     self.check_delay_seconds = check_delay_seconds;
   }
 
+  pub fn set_restart_strategy(&mut self, restart_strategy: RestartStrategy) {
+    self.restart_strategy = restart_strategy;
+  }
+
+  pub fn set_restart_backoff_seconds(&mut self, seconds: f64) {
+    self.restart_backoff_seconds = seconds;
+  }
+
+  pub fn set_restart_backoff_max_seconds(&mut self, seconds: f64) {
+    self.restart_backoff_max_seconds = seconds;
+  }
+
+  pub fn set_restart_backoff_multiplier(&mut self, multiplier: f64) {
+    self.restart_backoff_multiplier = multiplier;
+  }
+
+  pub fn set_restart_backoff_jitter(&mut self, jitter: f64) {
+    self.restart_backoff_jitter = jitter;
+  }
+
+  pub fn set_restart_backoff_reset_seconds(&mut self, seconds: f64) {
+    self.restart_backoff_reset_seconds = seconds;
+  }
+
   pub fn set_status_file(&mut self, status_file: String) {
     self.status_file = Some(status_file);
   }
 
-  pub fn set_start_complete(&mut self, command: String) {
+  pub fn set_status_format(&mut self, status_format: StatusFormat) {
+    self.status_format = status_format;
+  }
+
+  pub fn set_control_socket(&mut self, control_socket: String) {
+    self.control_socket = Some(control_socket);
+  }
+
+  pub fn set_output(&mut self, output: OutputMode) {
+    self.output = output;
+  }
+
+  pub fn set_output_color(&mut self, output_color: ColorMode) {
+    self.output_color = output_color;
+  }
+
+  pub fn set_cgroup_parent(&mut self, cgroup_parent: String) {
+    self.cgroup_parent = Some(cgroup_parent);
+  }
+
+  pub fn set_start_complete(&mut self, command: Command) {
     self.start_complete = Some(command);
   }
 
@@ -201,7 +833,7 @@ impl SupervisorSpecBuilder {
     set_optional_timeout(&mut self.start_complete_timeout, timeout_seconds)
   }
 
-  pub fn set_failure(&mut self, command: String) {
+  pub fn set_failure(&mut self, command: Command) {
     self.failure = Some(command);
   }
 
@@ -209,7 +841,7 @@ impl SupervisorSpecBuilder {
     set_optional_timeout(&mut self.failure_timeout, timeout_seconds)
   }
 
-  pub fn set_restart(&mut self, command: String) {
+  pub fn set_restart(&mut self, command: Command) {
     self.restart = Some(command);
   }
 
@@ -217,6 +849,10 @@ impl SupervisorSpecBuilder {
     set_optional_timeout(&mut self.restart_timeout, timeout_seconds)
   }
 
+  pub fn environment(&mut self) -> &mut EnvSpecBuilder {
+    &mut self.environment
+  }
+
   pub fn add_proc_spec(&mut self, spec: ProcSpec) {
     self.procs.push(spec);
   }
@@ -226,16 +862,35 @@ impl SupervisorSpecBuilder {
       restart_tokens_per_second: self.restart_tokens_per_second,
       check_delay_seconds: self.check_delay_seconds,
       max_restart_tokens: self.max_restart_tokens,
+      restart_strategy: self.restart_strategy,
+      restart_backoff_seconds: self.restart_backoff_seconds,
+      restart_backoff_max_seconds: self.restart_backoff_max_seconds,
+      restart_backoff_multiplier: self.restart_backoff_multiplier,
+      restart_backoff_jitter: self.restart_backoff_jitter,
+      restart_backoff_reset_seconds: self.restart_backoff_reset_seconds,
       status_file: self.status_file,
+      status_format: self.status_format,
+      control_socket: self.control_socket,
+      output: self.output,
+      output_color: self.output_color,
+      cgroup_parent: self.cgroup_parent,
       start_complete: self.start_complete,
       start_complete_timeout: self.start_complete_timeout,
       restart: self.restart,
       restart_timeout: self.restart_timeout,
       failure: self.failure,
       failure_timeout: self.failure_timeout,
+      environment: self.environment.build()?,
       procs: vec![],
     };
 
+    // Names identify a process in control commands and name its cgroup.
+    for (i, p) in self.procs.iter().enumerate() {
+      if self.procs[..i].iter().any(|q| q.name == p.name) {
+        return Err(SpecError::DuplicateName(p.name.clone()));
+      }
+    }
+
     spec.procs = self.procs;
 
     Ok(spec)
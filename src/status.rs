@@ -0,0 +1,78 @@
+use serde::Serialize;
+use std::os::unix::process::ExitStatusExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+  Stopped,
+  Starting,
+  Waiting,
+  Running,
+  Checking,
+  ShuttingDown,
+  Killing,
+  Cleaning,
+}
+
+impl Phase {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Phase::Stopped => "stopped",
+      Phase::Starting => "starting",
+      Phase::Waiting => "waiting",
+      Phase::Running => "running",
+      Phase::Checking => "checking",
+      Phase::ShuttingDown => "shutting_down",
+      Phase::Killing => "killing",
+      Phase::Cleaning => "cleaning",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ExitStatus {
+  pub code: Option<i32>,
+  pub signal: Option<i32>,
+}
+
+impl From<std::process::ExitStatus> for ExitStatus {
+  fn from(status: std::process::ExitStatus) -> Self {
+    ExitStatus {
+      code: status.code(),
+      signal: status.signal(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CheckResult {
+  pub ok: bool,
+  pub time: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SupervisorStatus {
+  pub state: &'static str,
+  pub time: f64,
+  pub restart_tokens: f64,
+  pub procs: Vec<ProcStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProcStatus {
+  pub name: String,
+  pub phase: Phase,
+  pub pid: Option<u32>,
+  pub restarts: u64,
+  pub last_exit: Option<ExitStatus>,
+  pub last_check: Option<CheckResult>,
+  pub status_text: Option<String>,
+}
+
+// Seconds since the unix epoch, as used for all status timestamps.
+pub fn now() -> f64 {
+  match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+    Ok(d) => d.as_secs_f64(),
+    Err(_) => 0.0,
+  }
+}
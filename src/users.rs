@@ -0,0 +1,197 @@
+// Users and groups are looked up when the spec is built, so a typo
+// fails at startup rather than on every restart. Only the setgroups,
+// setgid and setuid calls happen between fork and exec.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+  uid: Option<libc::uid_t>,
+  gid: Option<libc::gid_t>,
+  groups: Option<Vec<libc::gid_t>>,
+}
+
+struct User {
+  // None for a numeric uid without a passwd entry.
+  name: Option<String>,
+  uid: libc::uid_t,
+  gid: Option<libc::gid_t>,
+}
+
+fn lookup_user(user: &str) -> Result<User, String> {
+  let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+  let mut buf = vec![0 as libc::c_char; 16384];
+  let mut result = std::ptr::null_mut();
+  let uid = user.parse::<libc::uid_t>().ok();
+  let rc = match uid {
+    Some(uid) => unsafe {
+      libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result)
+    },
+    None => {
+      let name = std::ffi::CString::new(user).map_err(|_| format!("invalid user '{}'", user))?;
+      unsafe {
+        libc::getpwnam_r(
+          name.as_ptr(),
+          &mut pwd,
+          buf.as_mut_ptr(),
+          buf.len(),
+          &mut result,
+        )
+      }
+    }
+  };
+  if rc != 0 {
+    return Err(format!("unable to look up user '{}'", user));
+  }
+  if result.is_null() {
+    // Containers often run as a uid that is not in /etc/passwd.
+    return match uid {
+      Some(uid) => Ok(User {
+        name: None,
+        uid,
+        gid: None,
+      }),
+      None => Err(format!("no user named '{}'", user)),
+    };
+  }
+  Ok(User {
+    name: Some(
+      unsafe { std::ffi::CStr::from_ptr(pwd.pw_name) }
+        .to_string_lossy()
+        .into_owned(),
+    ),
+    uid: pwd.pw_uid,
+    gid: Some(pwd.pw_gid),
+  })
+}
+
+fn lookup_group(group: &str) -> Result<libc::gid_t, String> {
+  if let Ok(gid) = group.parse::<libc::gid_t>() {
+    return Ok(gid);
+  }
+  let mut grp: libc::group = unsafe { std::mem::zeroed() };
+  let mut buf = vec![0 as libc::c_char; 16384];
+  let mut result = std::ptr::null_mut();
+  let name = std::ffi::CString::new(group).map_err(|_| format!("invalid group '{}'", group))?;
+  let rc = unsafe {
+    libc::getgrnam_r(
+      name.as_ptr(),
+      &mut grp,
+      buf.as_mut_ptr(),
+      buf.len(),
+      &mut result,
+    )
+  };
+  if rc != 0 || result.is_null() {
+    return Err(format!("no group named '{}'", group));
+  }
+  Ok(grp.gr_gid)
+}
+
+fn user_groups(user: &User) -> Result<Vec<libc::gid_t>, String> {
+  // Without a passwd entry the user is in no group database either.
+  let (user_name, gid) = match (&user.name, user.gid) {
+    (Some(name), Some(gid)) => (name, gid),
+    _ => return Ok(vec![]),
+  };
+  let name = std::ffi::CString::new(user_name.clone()).unwrap_or_default();
+  let mut n: libc::c_int = 64;
+  loop {
+    let mut groups = vec![0 as libc::gid_t; n as usize];
+    let rc = unsafe { libc::getgrouplist(name.as_ptr(), gid, groups.as_mut_ptr(), &mut n) };
+    if rc >= 0 {
+      groups.truncate(n as usize);
+      return Ok(groups);
+    }
+    if n as usize <= groups.len() {
+      return Err(format!("unable to list groups of '{}'", user_name));
+    }
+  }
+}
+
+// A user without a group runs with that user's primary group, and
+// without supplementary groups gets the user's groups from the group
+// database, the same as a login would.
+pub fn resolve(
+  user: Option<&str>,
+  group: Option<&str>,
+  groups: Option<&[String]>,
+) -> Result<Option<Credentials>, String> {
+  if user.is_none() && group.is_none() && groups.is_none() {
+    return Ok(None);
+  }
+
+  let user = match user {
+    Some(user) => Some(lookup_user(user)?),
+    None => None,
+  };
+
+  let gid = match (group, &user) {
+    (Some(group), _) => Some(lookup_group(group)?),
+    (None, Some(user)) => match user.gid {
+      Some(gid) => Some(gid),
+      None => {
+        return Err(format!(
+          "user {} has no passwd entry, a group must be given",
+          user.uid
+        ))
+      }
+    },
+    (None, None) => None,
+  };
+
+  let groups = match (groups, &user) {
+    (Some(groups), _) => Some(
+      groups
+        .iter()
+        .map(|g| lookup_group(g))
+        .collect::<Result<Vec<_>, _>>()?,
+    ),
+    (None, Some(user)) => Some(user_groups(user)?),
+    (None, None) => None,
+  };
+
+  Ok(Some(Credentials {
+    uid: user.map(|u| u.uid),
+    gid,
+    groups,
+  }))
+}
+
+// Replaces only the primary group, keeping the uid and supplementary
+// groups of base.
+pub fn with_group(base: Option<&Credentials>, group: &str) -> Result<Credentials, String> {
+  let gid = lookup_group(group)?;
+  Ok(match base {
+    Some(base) => Credentials {
+      gid: Some(gid),
+      ..base.clone()
+    },
+    None => Credentials {
+      uid: None,
+      gid: Some(gid),
+      groups: None,
+    },
+  })
+}
+
+impl Credentials {
+  // The uid is changed last, as after that we may no longer be allowed
+  // to change groups.
+  pub unsafe fn apply(&self) -> Result<(), std::io::Error> {
+    if let Some(ref groups) = self.groups {
+      if libc::setgroups(groups.len() as _, groups.as_ptr()) != 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+    }
+    if let Some(gid) = self.gid {
+      if libc::setgid(gid) != 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+    }
+    if let Some(uid) = self.uid {
+      if libc::setuid(uid) != 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+    }
+    Ok(())
+  }
+}